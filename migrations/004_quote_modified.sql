@@ -0,0 +1,9 @@
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS updated_at timestamp with time zone NOT NULL DEFAULT now();
+
+CREATE INDEX IF NOT EXISTS quotes_updated_at ON quotes(updated_at);
+
+-- removals leave no row behind, so the last one is kept here
+CREATE TABLE IF NOT EXISTS quotes_removed(
+  id boolean PRIMARY KEY DEFAULT true CHECK (id),
+  removed_at timestamp with time zone NOT NULL
+);
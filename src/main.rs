@@ -1,16 +1,32 @@
 mod modules;
 mod utils;
 
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::from_fn_with_state;
+use axum::response::Response;
+use axum::routing::{get, RouterIntoService};
 use axum::Router;
+use jsonwebtoken::Algorithm;
+use serde::Serialize;
 use shuttle_persist::PersistInstance;
+use subtle::ConstantTimeEq;
 use tower_http::services::ServeDir;
 use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
 use tower_http::LatencyUnit;
 use tracing::Level;
 
+use utils::concurrency_limit::{limit_concurrency, max_concurrency};
+use utils::header_limit::{limit_header_bytes, max_header_bytes};
+use utils::utf8_body::require_utf8;
+
 use modules::{
     day_five, day_negative_one, day_nine, day_nineteen, day_sixteen, day_twelve, day_twenty_three,
-    day_two,
+    day_two, version,
 };
 
 #[shuttle_runtime::main]
@@ -36,18 +52,233 @@ async fn main(
                 .latency_unit(LatencyUnit::Micros),
         );
     tracing::info!("tracing is initialized");
+    utils::error_responses::ErrorFormat::try_from_env()?;
+    let service_config = ServiceConfig::from_env()?;
 
     let router = Router::new()
         .nest_service("/assets", ServeDir::new("resources/public"))
         .nest_service("/", day_negative_one::routes())
         .nest_service("/2", day_two::routes())
-        .nest_service("/5", day_five::routes())
-        .nest_service("/9", day_nine::routes())
-        .nest_service("/12", day_twelve::routes())
-        .nest_service("/16", day_sixteen::routes())
-        .nest_service("/19", day_nineteen::routes(pool, persist))
+        .nest_service("/5", require_utf8(day_five::routes()))
+        .nest_service("/9", require_utf8(day_nine::routes(persist.clone())))
+        .nest_service("/12", day_twelve::routes(persist.clone()))
+        .nest_service("/16", day_sixteen::routes()?)
+        .nest_service(
+            "/19",
+            limit_concurrency(
+                require_utf8(day_nineteen::routes(pool, persist)),
+                max_concurrency(),
+            ),
+        )
         .nest_service("/23", day_twenty_three::routes())
-        .layer(trace_layer);
+        .nest_service("/version", version::routes())
+        .nest_service("/config", config_routes(admin_token(), service_config))
+        .layer(trace_layer)
+        .layer(from_fn_with_state(max_header_bytes(), limit_header_bytes));
 
     Ok(router.into())
 }
+
+/// Environment variable holding the bearer token `/config` requires; the
+/// endpoint is disabled without one
+pub const ADMIN_TOKEN_ENV: &str = "ADMIN_TOKEN";
+
+fn admin_token() -> Option<String> {
+    std::env::var(ADMIN_TOKEN_ENV)
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+#[derive(Clone)]
+struct ConfigState {
+    admin_token: Option<String>,
+    /// Read once at startup, like every module reads its own configuration
+    config: Arc<ServiceConfig>,
+}
+
+fn config_routes(admin_token: Option<String>, config: ServiceConfig) -> RouterIntoService<Body> {
+    Router::new()
+        .route("/", get(service_config))
+        .with_state(ConfigState {
+            admin_token,
+            config: Arc::new(config),
+        })
+        .into_service()
+}
+
+/// Effective non-secret configuration of every module, keys and the admin
+/// token itself are never included
+#[derive(Debug, Serialize)]
+struct ServiceConfig {
+    log_level: Option<String>,
+    error_format: utils::error_responses::ErrorFormat,
+    max_header_bytes: usize,
+    max_concurrency: usize,
+    milk: MilkConfig,
+    manifest: ManifestConfig,
+    connect_four: ConnectFourConfig,
+    jwt: JwtConfig,
+    quotes: QuoteConfig,
+}
+
+#[derive(Debug, Serialize)]
+struct MilkConfig {
+    rate_limit_max: usize,
+    rate_limit_refill: usize,
+    rate_limit_interval_ms: u128,
+    auto_refill_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestConfig {
+    max_bytes: usize,
+    default_quantity: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct ConnectFourConfig {
+    rows: usize,
+    cols: usize,
+    max_games: usize,
+    game_idle_timeout_secs: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct JwtConfig {
+    cookie_name: String,
+    max_claims_depth: usize,
+    decode_algorithms: Vec<Algorithm>,
+}
+
+#[derive(Debug, Serialize)]
+struct QuoteConfig {
+    page_size: i32,
+    list_cache_ttl_ms: u128,
+    author_rate_limit: usize,
+    author_normalization: utils::quote::AuthorNormalization,
+    audit_redact: bool,
+}
+
+impl ServiceConfig {
+    fn from_env() -> anyhow::Result<Self> {
+        use utils::rate_limit::{BUCKET_MAX, BUCKET_REFILL, BUCKET_REFILL_INTERVAL};
+
+        let manifest = utils::cargo_manifest::ManifestConfig::from_env();
+        Ok(Self {
+            log_level: std::env::var("RUST_LOG").ok(),
+            error_format: utils::error_responses::ErrorFormat::from_env(),
+            max_header_bytes: max_header_bytes(),
+            max_concurrency: max_concurrency(),
+            milk: MilkConfig {
+                rate_limit_max: BUCKET_MAX,
+                rate_limit_refill: BUCKET_REFILL,
+                rate_limit_interval_ms: BUCKET_REFILL_INTERVAL.as_millis(),
+                auto_refill_secs: utils::rate_limit::auto_refill_interval()
+                    .map(|interval| interval.as_secs()),
+            },
+            manifest: ManifestConfig {
+                max_bytes: manifest.max_bytes,
+                default_quantity: manifest.default_quantity,
+            },
+            connect_four: ConnectFourConfig {
+                rows: day_twelve::board_size().0,
+                cols: day_twelve::board_size().1,
+                max_games: day_twelve::max_games(),
+                game_idle_timeout_secs: day_twelve::GAME_IDLE_TIMEOUT.as_secs(),
+            },
+            jwt: JwtConfig {
+                cookie_name: day_sixteen::jwt_cookie_name(),
+                max_claims_depth: day_sixteen::max_claims_depth(),
+                decode_algorithms: day_sixteen::decode_algorithms()?,
+            },
+            quotes: QuoteConfig {
+                page_size: utils::quote::PAGE_SIZE,
+                list_cache_ttl_ms: utils::quote::list_cache_ttl().as_millis(),
+                author_rate_limit: utils::quote::author_rate_limit(),
+                author_normalization: utils::quote::AuthorNormalization::from_env(),
+                audit_redact: utils::quote::audit_redact(),
+            },
+        })
+    }
+}
+
+async fn service_config(
+    State(state): State<ConfigState>,
+    headers: HeaderMap,
+) -> utils::error_handling::Result<Response> {
+    let Some(admin_token) = &state.admin_token else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())?);
+    };
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // constant time, so response timing doesn't leak how much of a guess matched
+    let authorized =
+        bearer.is_some_and(|bearer| bool::from(bearer.as_bytes().ct_eq(admin_token.as_bytes())));
+    if !authorized {
+        return Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::empty())?);
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(&*state.config)?.into())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    async fn get_config(admin_token: Option<&str>, bearer: Option<&str>) -> (StatusCode, String) {
+        let mut request = Request::get("/");
+        if let Some(bearer) = bearer {
+            request = request.header(AUTHORIZATION, format!("Bearer {bearer}"));
+        }
+        let config = ServiceConfig::from_env().unwrap();
+        let response = config_routes(admin_token.map(str::to_string), config)
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_config_redacts_secrets() {
+        let (status, body) = get_config(Some("letmein"), Some("letmein")).await;
+        assert_eq!(status, StatusCode::OK);
+        let config: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(config["milk"]["rate_limit_max"], 5);
+        assert_eq!(config["quotes"]["page_size"], 3);
+        assert!(!body.contains("SUPER_SECRET_KEY"));
+        assert!(!body.contains("PRIVATE KEY") && !body.contains("PUBLIC KEY"));
+        assert!(!body.contains("letmein"));
+    }
+
+    #[tokio::test]
+    async fn test_config_requires_admin_token() {
+        assert_eq!(
+            get_config(Some("letmein"), None).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+        for guess in ["guess", "letme", "letmeinx", ""] {
+            assert_eq!(
+                get_config(Some("letmein"), Some(guess)).await.0,
+                StatusCode::UNAUTHORIZED,
+                "{guess}"
+            );
+        }
+        assert_eq!(get_config(None, Some("")).await.0, StatusCode::NOT_FOUND);
+    }
+}
@@ -1,25 +1,378 @@
 use axum::body::Body;
-use axum::http::StatusCode;
+use axum::extract::Query;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderValue, StatusCode};
 use axum::response::Response;
 use axum::routing::{post, RouterIntoService};
 use axum::Router;
+use serde::Deserialize;
 
-use crate::utils::cargo_manifest::Metadata;
-use crate::utils::error_responses::no_content;
+use crate::utils::cargo_manifest::{ManifestConfig, ManifestFormat, Metadata};
+use crate::utils::error_handling::Result;
+use crate::utils::error_responses::{bad_request, no_content};
 
 pub fn routes() -> RouterIntoService<Body> {
+    router(ManifestConfig::from_env())
+}
+
+fn router(config: ManifestConfig) -> RouterIntoService<Body> {
     Router::new()
         .route("/manifest", post(manifest))
+        .route("/convert", post(convert))
+        .with_state(config)
         .into_service()
 }
 
-async fn manifest(metadata: Metadata) -> Response {
-    if metadata.orders.is_empty() {
+#[derive(Debug, Deserialize)]
+struct ManifestQuery {
+    /// Sum the quantities of orders for the same item
+    #[serde(default)]
+    merge: bool,
+    #[serde(default)]
+    empty: EmptyOrders,
+}
+
+/// Response for a manifest without any orders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum EmptyOrders {
+    #[default]
+    NoContent,
+    /// A 200 with an empty body, for clients that don't handle 204
+    Ok,
+}
+
+async fn manifest(Query(query): Query<ManifestQuery>, metadata: Metadata) -> Response {
+    let metadata = if query.merge {
+        metadata.merged()
+    } else {
+        metadata
+    };
+
+    if metadata.orders.is_empty() && query.empty == EmptyOrders::NoContent {
         return no_content();
     }
 
-    Response::builder()
+    let mut response = Response::builder().status(StatusCode::OK);
+    if !metadata.warnings.is_empty() {
+        response = response.header(
+            MANIFEST_WARNINGS_HEADER,
+            warnings_header(&metadata.warnings),
+        );
+    }
+    response.body(metadata.to_string().into()).unwrap()
+}
+
+/// Response header listing the manifest's warnings as a JSON array
+pub const MANIFEST_WARNINGS_HEADER: &str = "x-manifest-warnings";
+
+/// `warnings` as a JSON array that is always a valid header value; JSON
+/// already escapes every control character but DEL
+fn warnings_header(warnings: &[String]) -> HeaderValue {
+    let json = serde_json::json!(warnings)
+        .to_string()
+        .replace('\u{7f}', "\\u007f");
+    HeaderValue::from_str(&json).expect("JSON without control characters is a valid header")
+}
+
+#[derive(Debug, Deserialize)]
+struct ConvertQuery {
+    /// Output format, `toml`, `json` or `yaml`
+    to: String,
+}
+
+async fn convert(Query(query): Query<ConvertQuery>, metadata: Metadata) -> Result<Response> {
+    let Some(format) = ManifestFormat::from_extension(&query.to.to_lowercase()) else {
+        return Ok(bad_request());
+    };
+
+    Ok(Response::builder()
         .status(StatusCode::OK)
-        .body(metadata.to_string().into())
-        .unwrap()
+        .header(CONTENT_TYPE, format.content_type())
+        .body(metadata.serialize(format)?.into())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    async fn post_manifest(body: String) -> StatusCode {
+        post_toml(body).await.0
+    }
+
+    async fn post_toml(body: String) -> (StatusCode, String) {
+        post_toml_to("/manifest", body).await
+    }
+
+    async fn post_toml_to(uri: &str, body: String) -> (StatusCode, String) {
+        let request = Request::post(uri)
+            .header(CONTENT_TYPE, "application/toml")
+            .body(Body::from(body))
+            .unwrap();
+        let response = routes().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    async fn post_octet_stream(filename: &str, body: &str) -> (StatusCode, String) {
+        let request = Request::post("/manifest")
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header(
+                CONTENT_DISPOSITION,
+                format!(r#"attachment; filename="{filename}""#),
+            )
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = routes().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    const JSON_MANIFEST: &str = r#"{
+        "package": {
+            "name": "not-a-gift-order",
+            "version": "0.1.0",
+            "keywords": ["Christmas 2024"],
+            "metadata": {"orders": [{"item": "Toy car", "quantity": 2}]}
+        }
+    }"#;
+
+    #[tokio::test]
+    async fn test_manifest_warnings() {
+        let manifest = toml_manifest(
+            r#"[[package.metadata.orders]]
+item = "Toy car"
+quantity = 0
+
+[[package.metadata.orders]]
+item = "Lego brick"
+quantity = 230
+"#,
+        );
+        let request = Request::post("/manifest")
+            .header(CONTENT_TYPE, "application/toml")
+            .body(Body::from(manifest))
+            .unwrap();
+        let response = routes().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let warnings = response.headers()[MANIFEST_WARNINGS_HEADER]
+            .to_str()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Vec<String>>(warnings).unwrap(),
+            vec!["Toy car: quantity is 0"]
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "Toy car: 0\nLego brick: 230");
+
+        let response = routes()
+            .oneshot(
+                Request::post("/manifest")
+                    .header(CONTENT_TYPE, "application/toml")
+                    .body(Body::from(toml_manifest(TOML_ORDERS)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(!response.headers().contains_key(MANIFEST_WARNINGS_HEADER));
+    }
+
+    #[tokio::test]
+    async fn test_missing_quantity_uses_configured_default() {
+        let manifest = toml_manifest(
+            r#"[[package.metadata.orders]]
+item = "Toy car"
+"#,
+        );
+        for (default_quantity, expected) in [(Some(1), "Toy car: 1"), (None, "")] {
+            let request = Request::post("/manifest")
+                .header(CONTENT_TYPE, "application/toml")
+                .body(Body::from(manifest.clone()))
+                .unwrap();
+            let response = router(ManifestConfig {
+                default_quantity,
+                ..ManifestConfig::default()
+            })
+            .oneshot(request)
+            .await
+            .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, expected, "{default_quantity:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_manifest() {
+        let body = format!("# {}", "🎄".repeat(512 * 1024));
+        assert_eq!(post_manifest(body).await, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_manifest() {
+        let body = "[package\nname = ".to_string();
+        assert_eq!(post_manifest(body).await, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_octet_stream_json_filename() {
+        let (status, body) = post_octet_stream("Cargo.json", JSON_MANIFEST).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "Toy car: 2");
+    }
+
+    #[tokio::test]
+    async fn test_octet_stream_unknown_extension() {
+        let (status, _) = post_octet_stream("Cargo.txt", JSON_MANIFEST).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn test_configured_extensions_and_max_bytes() {
+        let toml_only = ManifestConfig {
+            allowed_extensions: ["toml".to_string()].into(),
+            ..ManifestConfig::default()
+        };
+        let json = Request::post("/manifest")
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header(CONTENT_DISPOSITION, r#"attachment; filename="Cargo.json""#)
+            .body(Body::from(JSON_MANIFEST))
+            .unwrap();
+        let response = router(toml_only).oneshot(json).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let small = ManifestConfig {
+            max_bytes: 64,
+            ..ManifestConfig::default()
+        };
+        let toml = Request::post("/manifest")
+            .header(CONTENT_TYPE, "application/toml")
+            .body(Body::from(toml_manifest(TOML_ORDERS)))
+            .unwrap();
+        let response = router(small).oneshot(toml).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    fn toml_manifest(orders: &str) -> String {
+        format!(
+            "[package]\nname = \"not-a-gift-order\"\nversion = \"0.1.0\"\nkeywords = [\"Christmas 2024\"]\n\n{orders}"
+        )
+    }
+
+    #[tokio::test]
+    async fn test_orders_array_and_map_equivalent() {
+        let array = toml_manifest(
+            r#"[[package.metadata.orders]]
+item = "Toy car"
+quantity = 2
+
+[[package.metadata.orders]]
+item = "Lego brick"
+quantity = 230
+"#,
+        );
+        let map = toml_manifest(
+            r#"[package.metadata.orders]
+"Toy car" = 2
+"Lego brick" = 230
+"Broken" = "many"
+"#,
+        );
+
+        let (status, array_body) = post_toml(array).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, map_body) = post_toml(map).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let sorted = |body: &str| {
+            let mut lines = body.lines().map(str::to_string).collect::<Vec<_>>();
+            lines.sort();
+            lines
+        };
+        assert_eq!(sorted(&array_body), vec!["Lego brick: 230", "Toy car: 2"]);
+        assert_eq!(sorted(&map_body), sorted(&array_body));
+    }
+
+    const TOML_ORDERS: &str = r#"[[package.metadata.orders]]
+item = "Toy car"
+quantity = 2
+
+[[package.metadata.orders]]
+item = "Lego brick"
+quantity = 230
+"#;
+
+    #[tokio::test]
+    async fn test_convert_toml_to_json() {
+        let (status, body) = post_toml_to("/convert?to=json", toml_manifest(TOML_ORDERS)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({"orders": [
+                {"item": "Toy car", "quantity": 2},
+                {"item": "Lego brick", "quantity": 230},
+            ]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_convert_unknown_format() {
+        let (status, _) = post_toml_to("/convert?to=xml", toml_manifest(TOML_ORDERS)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    const DUPLICATE_ORDERS: &str = r#"[[package.metadata.orders]]
+item = "Toy car"
+quantity = 2
+
+[[package.metadata.orders]]
+item = "Lego brick"
+quantity = 230
+
+[[package.metadata.orders]]
+item = "Toy car"
+quantity = 3
+"#;
+
+    #[tokio::test]
+    async fn test_manifest_duplicates_kept_by_default() {
+        let (status, body) = post_toml(toml_manifest(DUPLICATE_ORDERS)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "Toy car: 2\nLego brick: 230\nToy car: 3");
+    }
+
+    #[tokio::test]
+    async fn test_manifest_merge_duplicates() {
+        let (status, body) =
+            post_toml_to("/manifest?merge=true", toml_manifest(DUPLICATE_ORDERS)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "Toy car: 5\nLego brick: 230");
+    }
+
+    #[tokio::test]
+    async fn test_manifest_without_orders() {
+        let manifest = toml_manifest("[package.metadata]\norders = []\n");
+        let (status, body) = post_toml(manifest.clone()).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert_eq!(body, "");
+
+        for manifest in [manifest, toml_manifest("")] {
+            let (status, body) = post_toml_to("/manifest?empty=ok", manifest).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body, "");
+        }
+    }
 }
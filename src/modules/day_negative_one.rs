@@ -1,7 +1,8 @@
 use axum::body::Body;
-use axum::http::{header, StatusCode};
-use axum::response::IntoResponse;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, RouterIntoService};
+use axum::Json;
 use axum::Router;
 
 pub fn routes() -> RouterIntoService<Body> {
@@ -11,9 +12,44 @@ pub fn routes() -> RouterIntoService<Body> {
         .into_service()
 }
 
+const GREETING: &str = "Hello, bird!";
+
 #[tracing::instrument]
-async fn hello_bird() -> &'static str {
-    "Hello, bird!"
+async fn hello_bird(headers: HeaderMap) -> Response {
+    if prefers_json(&headers) {
+        Json(serde_json::json!({ "message": GREETING })).into_response()
+    } else {
+        GREETING.into_response()
+    }
+}
+
+/// Whether the `Accept` header ranks JSON strictly above plain text, so that
+/// wildcards and missing headers keep the original text response
+fn prefers_json(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+    else {
+        return false;
+    };
+
+    let quality = |media_type: &str| {
+        let (kind, _) = media_type.split_once('/').unwrap_or((media_type, ""));
+        accept
+            .split(',')
+            .filter_map(|range| {
+                let mut params = range.split(';').map(str::trim);
+                let range = params.next()?.to_ascii_lowercase();
+                let matches = range == media_type || range == format!("{kind}/*") || range == "*/*";
+                let q = params
+                    .find_map(|param| param.strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+                matches.then_some(q)
+            })
+            .fold(0.0, f32::max)
+    };
+
+    quality("application/json") > quality("text/plain")
 }
 
 const REDIRECT_URL: &str = "https://www.youtube.com/watch?v=9Gc4QTqslN4";
@@ -22,3 +58,51 @@ const REDIRECT_URL: &str = "https://www.youtube.com/watch?v=9Gc4QTqslN4";
 async fn seek() -> impl IntoResponse {
     (StatusCode::FOUND, [(header::LOCATION, REDIRECT_URL)])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    async fn hello(accept: Option<&str>) -> (Option<String>, String) {
+        let mut request = Request::get("/");
+        if let Some(accept) = accept {
+            request = request.header(header::ACCEPT, accept);
+        }
+        let response = routes()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|content_type| content_type.to_str().unwrap().to_string());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (content_type, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_hello_bird_json() {
+        let (content_type, body) = hello(Some("application/json")).await;
+        assert_eq!(content_type.as_deref(), Some("application/json"));
+        assert_eq!(body, r#"{"message":"Hello, bird!"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_hello_bird_text() {
+        for accept in [
+            None,
+            Some("text/plain"),
+            Some("*/*"),
+            Some("application/json;q=0"),
+        ] {
+            let (content_type, body) = hello(accept).await;
+            assert!(content_type.unwrap().starts_with("text/plain"));
+            assert_eq!(body, "Hello, bird!");
+        }
+    }
+}
@@ -1,25 +1,67 @@
+use std::collections::HashMap;
 use std::ops::DerefMut;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 use axum::body::Body;
-use axum::extract::State;
+use axum::extract::{Form, FromRequest, Query, Request, State};
 use axum::http::header::CONTENT_TYPE;
-use axum::http::{HeaderMap, StatusCode};
+use axum::http::StatusCode;
 use axum::response::Response;
-use axum::routing::{post, RouterIntoService};
-use axum::{Json, Router};
+use axum::routing::{get, post, RouterIntoService};
+use axum::Router;
 use serde::{Deserialize, Serialize};
+use shuttle_persist::PersistInstance;
+use tokio::sync::Mutex;
 
 use crate::utils::error_handling::Result;
-use crate::utils::rate_limit::{filled_bucket, RateLimit};
+use crate::utils::error_responses::bad_request;
+use crate::utils::extractors::JsonOrWithdrawn;
+use crate::utils::persist_namespace::NamespacedPersist;
+use crate::utils::rate_limit::{auto_refill_interval, filled_bucket, RateLimit};
+
+/// Persist key of the number of successful `/milk` conversions
+const SERVED_KEY: &str = "milk_served";
+
+/// Environment variable holding the namespace of milk keys in the shared persist store
+pub const PERSIST_PREFIX_ENV: &str = "MILK_PERSIST_PREFIX";
+
+const DEFAULT_PERSIST_PREFIX: &str = "milk:";
+
+pub fn routes(persist: PersistInstance) -> RouterIntoService<Body> {
+    let rate_limit = RateLimit::default();
+    if let Some(interval) = auto_refill_interval() {
+        tracing::info!("auto-refilling milk every {interval:?}");
+        rate_limit.spawn_auto_refill(interval);
+    }
+    let persist = NamespacedPersist::from_env(persist, PERSIST_PREFIX_ENV, DEFAULT_PERSIST_PREFIX);
 
-pub fn routes() -> RouterIntoService<Body> {
     Router::new()
         .route("/milk", post(milk))
         .route("/refill", post(refill))
-        .with_state(RateLimit::default())
+        .route("/metrics", get(metrics))
+        .route("/preview", get(preview))
+        .route("/units", get(units))
+        .route("/served", get(served))
+        .with_state(MilkState {
+            rate_limit,
+            rejections: Arc::default(),
+            served: Arc::new(Mutex::new(persist.load(SERVED_KEY).unwrap_or(0))),
+            persist,
+        })
         .into_service()
 }
 
+#[derive(Debug, Clone)]
+struct MilkState {
+    rate_limit: RateLimit,
+    /// Number of `/milk` requests turned away because the bucket was empty
+    rejections: Arc<AtomicU64>,
+    /// Successful conversions across restarts, saved under `SERVED_KEY`
+    served: Arc<Mutex<u64>>,
+    persist: NamespacedPersist,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 enum MilkPayload {
@@ -37,6 +79,25 @@ impl From<MilkPayload> for Body {
 
 #[allow(clippy::excessive_precision)]
 impl MilkPayload {
+    /// Tag of every variant in declaration order, as accepted in payloads
+    const UNITS: [&'static str; 4] = ["gallons", "liters", "litres", "pints"];
+
+    fn from_unit(unit: &str, value: f32) -> Option<Self> {
+        match unit {
+            "gallons" => Some(Self::Gallons(value)),
+            "liters" => Some(Self::Liters(value)),
+            "litres" => Some(Self::Litres(value)),
+            "pints" => Some(Self::Pints(value)),
+            _ => None,
+        }
+    }
+
+    fn value(&self) -> f32 {
+        match self {
+            Self::Gallons(n) | Self::Liters(n) | Self::Litres(n) | Self::Pints(n) => *n,
+        }
+    }
+
     fn convert(&self) -> MilkPayload {
         match self {
             Self::Liters(n) => Self::Gallons(0.264172060 * n),
@@ -47,54 +108,175 @@ impl MilkPayload {
     }
 }
 
+/// Header naming the unit of a bare-number JSON body, e.g. `X-Milk-Unit: gallons`
+const MILK_UNIT_HEADER: &str = "x-milk-unit";
+
+/// Milk payload sent either as JSON, as a bare JSON number with its unit in
+/// `X-Milk-Unit`, or as a single-field form like `gallons=5`, with anything else
+/// withdrawn the same way `JsonOrWithdrawn` does
+struct MilkBody(MilkPayload);
+
+#[async_trait::async_trait]
+impl<S> FromRequest<S> for MilkBody
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> std::result::Result<Self, Self::Rejection> {
+        let is_form = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(|content_type| content_type.split(';').next())
+            .is_some_and(|essence| essence.trim() == "application/x-www-form-urlencoded");
+        if !is_form {
+            let unit = req
+                .headers()
+                .get(MILK_UNIT_HEADER)
+                .map(|unit| unit.to_str().map(str::to_string));
+            let Some(unit) = unit else {
+                let JsonOrWithdrawn(payload) = JsonOrWithdrawn::from_request(req, state).await?;
+                return Ok(Self(payload));
+            };
+            let JsonOrWithdrawn(value) = JsonOrWithdrawn::<f32>::from_request(req, state).await?;
+            return unit
+                .ok()
+                .and_then(|unit| MilkPayload::from_unit(&unit, value))
+                .map(Self)
+                .ok_or_else(bad_request);
+        }
+
+        let Form(fields) = Form::<HashMap<String, f32>>::from_request(req, state)
+            .await
+            .map_err(|_| bad_request())?;
+        let mut fields = fields.into_iter();
+        match (fields.next(), fields.next()) {
+            (Some((unit, value)), None) => MilkPayload::from_unit(&unit, value)
+                .map(Self)
+                .ok_or_else(bad_request),
+            _ => Err(bad_request()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MilkQuery {
+    /// Return both the original and converted payloads
+    #[serde(default)]
+    verbose: bool,
+}
+
+/// Verbose milk response carrying both units
+#[derive(Debug, Serialize)]
+struct MilkConversion {
+    from: MilkPayload,
+    to: MilkPayload,
+}
+
 async fn milk(
-    State(rate_limit): State<RateLimit>,
-    headers: HeaderMap,
-    payload: Option<Json<MilkPayload>>,
+    State(state): State<MilkState>,
+    Query(query): Query<MilkQuery>,
+    payload: std::result::Result<MilkBody, Response>,
 ) -> Result<Response> {
-    let has_milk = rate_limit.lock().await.try_acquire(1);
+    let has_milk = state.rate_limit.lock().await.try_acquire(1);
     if !has_milk {
+        state.rejections.fetch_add(1, Ordering::Relaxed);
         return too_many_requests();
     }
 
-    let Some(content_type) = headers.get(CONTENT_TYPE) else {
-        return milk_withdrawn();
+    let MilkBody(payload) = match payload {
+        Ok(payload) => payload,
+        Err(rejection) => return Ok(rejection),
     };
 
-    if content_type != "application/json" {
-        return milk_withdrawn();
+    // NaN and infinities only get in through forms, but huge values overflow
+    let converted = payload.convert();
+    if !payload.value().is_finite() || !converted.value().is_finite() {
+        return non_finite();
     }
 
-    let Some(Json(payload)) = payload else {
-        return bad_request();
+    let mut served = state.served.lock().await;
+    *served += 1;
+    state.persist.save(SERVED_KEY, *served)?;
+    drop(served);
+
+    if query.verbose {
+        return Ok(Response::builder().status(StatusCode::OK).body(Body::from(
+            serde_json::to_string(&MilkConversion {
+                from: payload,
+                to: converted,
+            })?,
+        ))?);
+    }
+
+    converted_milk(converted)
+}
+
+#[derive(Debug, Deserialize)]
+struct PreviewQuery {
+    from: String,
+    value: f32,
+}
+
+/// Converts like `/milk` without touching the bucket
+async fn preview(query: Option<Query<PreviewQuery>>) -> Result<Response> {
+    let Some(payload) =
+        query.and_then(|Query(query)| MilkPayload::from_unit(&query.from, query.value))
+    else {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())?);
     };
 
     converted_milk(payload.convert())
 }
 
-async fn refill(State(rate_limit): State<RateLimit>) -> Result<Response> {
-    let mut lock = rate_limit.lock().await;
+async fn refill(State(state): State<MilkState>) -> Result<Response> {
+    let mut lock = state.rate_limit.lock().await;
     let bucket = lock.deref_mut();
     *bucket = filled_bucket();
     ok()
 }
 
-fn too_many_requests() -> Result<Response> {
+async fn metrics(State(state): State<MilkState>) -> Result<Response> {
+    let rejections = state.rejections.load(Ordering::Relaxed);
     Ok(Response::builder()
-        .status(StatusCode::TOO_MANY_REQUESTS)
-        .body("No milk available\n".into())?)
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(
+            serde_json::json!({ "rejections": rejections })
+                .to_string()
+                .into(),
+        )?)
+}
+
+async fn served(State(state): State<MilkState>) -> Result<Response> {
+    let served = *state.served.lock().await;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "served": served }).to_string().into())?)
 }
 
-fn milk_withdrawn() -> Result<Response> {
+async fn units() -> Result<Response> {
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .body("Milk withdrawn\n".into())?)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(&MilkPayload::UNITS)?.into())?)
+}
+
+fn too_many_requests() -> Result<Response> {
+    Ok(Response::builder()
+        .status(StatusCode::TOO_MANY_REQUESTS)
+        .body("No milk available\n".into())?)
 }
 
-fn bad_request() -> Result<Response> {
+fn non_finite() -> Result<Response> {
     Ok(Response::builder()
         .status(StatusCode::BAD_REQUEST)
-        .body(Body::empty())?)
+        .header(CONTENT_TYPE, "application/json")
+        .body(r#"{"error":"non_finite"}"#.into())?)
 }
 
 fn converted_milk(payload: MilkPayload) -> Result<Response> {
@@ -108,3 +290,243 @@ fn ok() -> Result<Response> {
         .status(StatusCode::OK)
         .body(Body::empty())?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::http::Request;
+    use serde_json::{json, Value};
+    use tower::{Service, ServiceExt};
+
+    use crate::utils::session_store::random_id;
+
+    fn test_routes() -> RouterIntoService<Body> {
+        let dir = std::env::temp_dir().join(format!("cch24-day-nine-{}", random_id()));
+        routes(PersistInstance::new(dir).unwrap())
+    }
+
+    async fn post_milk(uri: &str, body: &str) -> (StatusCode, String) {
+        send(&mut test_routes(), milk_request(uri, body)).await
+    }
+
+    fn milk_request(uri: &str, body: &str) -> Request<Body> {
+        Request::post(uri)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn send(
+        service: &mut RouterIntoService<Body>,
+        request: Request<Body>,
+    ) -> (StatusCode, String) {
+        let response = service.ready().await.unwrap().call(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_milk_converted_only() {
+        let (status, body) = post_milk("/milk", r#"{"gallons":1}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"liters":3.78541}"#);
+    }
+
+    #[tokio::test]
+    async fn test_milk_verbose() {
+        let (status, body) = post_milk("/milk?verbose=true", r#"{"gallons":1}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({"from": {"gallons": 1.0}, "to": {"liters": 3.78541}})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejections_counted() {
+        let mut service = test_routes();
+        for _ in 0..5 {
+            let (status, _) = send(&mut service, milk_request("/milk", r#"{"gallons":1}"#)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        for _ in 0..2 {
+            let (status, _) = send(&mut service, milk_request("/milk", r#"{"gallons":1}"#)).await;
+            assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        }
+
+        let request = Request::get("/metrics").body(Body::empty()).unwrap();
+        let (status, body) = send(&mut service, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_str::<Value>(&body).unwrap(),
+            json!({"rejections": 2})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_preview_leaves_bucket_alone() {
+        let mut service = test_routes();
+        for _ in 0..10 {
+            let request = Request::get("/preview?from=gallons&value=1")
+                .body(Body::empty())
+                .unwrap();
+            let (status, body) = send(&mut service, request).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body, r#"{"liters":3.78541}"#);
+        }
+
+        // the bucket still holds all five milks
+        for _ in 0..5 {
+            let (status, _) = send(&mut service, milk_request("/milk", r#"{"gallons":1}"#)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let (status, _) = send(&mut service, milk_request("/milk", r#"{"gallons":1}"#)).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_preview_invalid_query() {
+        let mut service = test_routes();
+        for uri in [
+            "/preview?from=cups&value=1",
+            "/preview?from=gallons&value=lots",
+            "/preview?from=gallons",
+        ] {
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            let (status, _) = send(&mut service, request).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        }
+    }
+
+    #[test]
+    fn test_units_match_payload_tags() {
+        for unit in MilkPayload::UNITS {
+            let payload = MilkPayload::from_unit(unit, 1.0).unwrap();
+            let value = serde_json::to_value(payload).unwrap();
+            assert!(value.get(unit).is_some(), "{unit}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_served_persists() {
+        let dir = std::env::temp_dir().join(format!("cch24-day-nine-served-{}", random_id()));
+        let mut service = routes(PersistInstance::new(dir.clone()).unwrap());
+        let served = Request::get("/served").body(Body::empty()).unwrap();
+        let (_, body) = send(&mut service, served).await;
+        assert_eq!(body, r#"{"served":0}"#);
+
+        for _ in 0..2 {
+            let (status, _) = send(&mut service, milk_request("/milk", r#"{"gallons":1}"#)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let (status, _) = send(&mut service, milk_request("/milk", "{")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // a fresh router over the same storage picks the count back up
+        let mut restarted = routes(PersistInstance::new(dir).unwrap());
+        let served = Request::get("/served").body(Body::empty()).unwrap();
+        let (status, body) = send(&mut restarted, served).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"served":2}"#);
+    }
+
+    #[tokio::test]
+    async fn test_units() {
+        let request = Request::get("/units").body(Body::empty()).unwrap();
+        let (status, body) = send(&mut test_routes(), request).await;
+        assert_eq!(status, StatusCode::OK);
+        let units: Vec<String> = serde_json::from_str(&body).unwrap();
+        for unit in ["gallons", "liters", "litres", "pints"] {
+            assert!(units.iter().any(|u| u == unit), "{unit}");
+        }
+    }
+
+    fn unit_request(unit: &str, body: &str) -> Request<Body> {
+        Request::post("/milk")
+            .header(CONTENT_TYPE, "application/json")
+            .header(MILK_UNIT_HEADER, unit)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_milk_unit_header() {
+        let mut service = test_routes();
+        let (status, body) = send(&mut service, unit_request("gallons", "1")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"liters":3.78541}"#);
+
+        let (status, _) = send(&mut service, unit_request("cups", "1")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(&mut service, unit_request("gallons", r#"{"gallons":1}"#)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    fn form_request(body: &str) -> Request<Body> {
+        form_request_as("application/x-www-form-urlencoded", body)
+    }
+
+    fn form_request_as(content_type: &str, body: &str) -> Request<Body> {
+        Request::post("/milk")
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_milk_form_payload() {
+        let (status, body) = send(&mut test_routes(), form_request("gallons=1")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"liters":3.78541}"#);
+
+        let request = form_request_as(
+            "application/x-www-form-urlencoded; charset=UTF-8",
+            "gallons=1",
+        );
+        let (status, body) = send(&mut test_routes(), request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"liters":3.78541}"#);
+    }
+
+    #[tokio::test]
+    async fn test_milk_form_invalid_payload() {
+        for body in ["cups=1", "gallons=1&liters=2", "gallons=lots", ""] {
+            let (status, _) = send(&mut test_routes(), form_request(body)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_milk_overflowing_to_infinity() {
+        let mut service = test_routes();
+        let (status, body) = send(&mut service, milk_request("/milk", r#"{"gallons":3e38}"#)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, r#"{"error":"non_finite"}"#);
+
+        let (_, served) = send(
+            &mut service,
+            Request::get("/served").body(Body::empty()).unwrap(),
+        )
+        .await;
+        assert_eq!(served, r#"{"served":0}"#);
+    }
+
+    #[tokio::test]
+    async fn test_milk_non_finite_input() {
+        for value in ["NaN", "inf", "-inf"] {
+            let request = Request::post("/milk")
+                .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(format!("liters={value}")))
+                .unwrap();
+            let (status, body) = send(&mut test_routes(), request).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{value}");
+            assert_eq!(body, r#"{"error":"non_finite"}"#);
+        }
+    }
+}
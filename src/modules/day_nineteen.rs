@@ -1,29 +1,80 @@
+use std::collections::BTreeMap;
+
 use axum::body::Body;
-use axum::extract::{Path, Query, State};
-use axum::http::header::CONTENT_TYPE;
-use axum::http::StatusCode;
-use axum::response::Response;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{FromRequestParts, Path, Query, State};
+use axum::http::header::{CONTENT_TYPE, IF_MODIFIED_SINCE, LAST_MODIFIED, LOCATION, RETRY_AFTER};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{delete, get, post, put, RouterIntoService};
 use axum::{Json, Router};
+use html_escape::encode_text;
+use indoc::formatdoc;
 use serde::Deserialize;
 use shuttle_persist::PersistInstance;
+use sqlx::types::chrono::{DateTime, Utc};
 use sqlx::types::Uuid;
 
 use crate::utils::error_handling::Result;
-use crate::utils::quote::{ListResponse, QuotePayload, QuoteState};
+use crate::utils::quote::{
+    ListOrder, ListResponse, PgQuoteStore, Quote, QuotePayload, QuoteState, SearchMode,
+    FIRST_VERSION,
+};
 
 pub fn routes(pool: sqlx::PgPool, persist: PersistInstance) -> RouterIntoService<Body> {
+    router(QuoteState::new(Box::new(PgQuoteStore::new(pool)), persist))
+}
+
+fn router(state: QuoteState) -> RouterIntoService<Body> {
     Router::new()
         .route("/reset", post(reset))
+        .route("/reset-tokens", post(reset_tokens))
         .route("/cite/:id", get(cite))
+        .route("/cite/batch", post(cite_batch))
+        .route("/latest/:author", get(latest_by_author))
         .route("/remove/:id", delete(remove))
         .route("/undo/:id", put(undo))
         .route("/draft", post(draft))
         .route("/list", get(list))
-        .with_state(QuoteState::new(pool, persist))
+        .route("/recent", get(recent))
+        .route("/authors", get(authors))
+        .route("/stats", get(stats))
+        .route("/search", get(search))
+        .route("/feed.xml", get(feed))
+        .with_state(state)
         .into_service()
 }
 
+/// Quote id path parameter, rejecting malformed ids with a JSON 400
+struct QuoteId(Uuid);
+
+fn invalid_id() -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"error":"invalid_id"}"#))
+        .unwrap()
+}
+
+#[async_trait::async_trait]
+impl<S> FromRequestParts<S> for QuoteId
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        let Path(id) = Path::<Uuid>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| invalid_id())?;
+        Ok(Self(id))
+    }
+}
+
 async fn reset(State(state): State<QuoteState>) -> StatusCode {
     match state.reset().await {
         Ok(_) => StatusCode::OK,
@@ -31,7 +82,36 @@ async fn reset(State(state): State<QuoteState>) -> StatusCode {
     }
 }
 
-async fn cite(State(state): State<QuoteState>, Path(id): Path<Uuid>) -> Result<Response> {
+async fn reset_tokens(State(state): State<QuoteState>) -> StatusCode {
+    match state.reset_tokens() {
+        Ok(_) => StatusCode::OK,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Representation `/cite` embeds a quote in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CiteFormat {
+    #[default]
+    Json,
+    /// `> quote` lines followed by `> — author`
+    Markdown,
+    /// A `<blockquote>` with the author in a `<footer>`
+    Html,
+}
+
+#[derive(Debug, Deserialize)]
+struct CiteQuery {
+    #[serde(default)]
+    format: CiteFormat,
+}
+
+async fn cite(
+    State(state): State<QuoteState>,
+    QuoteId(id): QuoteId,
+    Query(query): Query<CiteQuery>,
+) -> Result<Response> {
     let quote = state.get_quote(id).await?;
 
     let Some(quote) = quote else {
@@ -40,12 +120,80 @@ async fn cite(State(state): State<QuoteState>, Path(id): Path<Uuid>) -> Result<R
             .body(Body::empty())?);
     };
 
+    match query.format {
+        CiteFormat::Json => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::from(serde_json::to_string_pretty(&quote)?))?),
+        CiteFormat::Markdown => {
+            let markdown = quote
+                .quote()
+                .lines()
+                .map(|line| format!("> {line}\n"))
+                .chain([format!("> — {}", quote.author())])
+                .collect::<String>();
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, "text/markdown; charset=utf-8")
+                .body(markdown.into())?)
+        }
+        CiteFormat::Html => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(
+                format!(
+                    "<blockquote><p>{}</p><footer>— {}</footer></blockquote>",
+                    encode_text(quote.quote()),
+                    encode_text(quote.author()),
+                )
+                .into(),
+            )?),
+    }
+}
+
+/// Most ids accepted by a single `/cite/batch` request
+const MAX_BATCH_IDS: usize = 100;
+
+async fn cite_batch(
+    State(state): State<QuoteState>,
+    Json(ids): Json<Vec<Uuid>>,
+) -> Result<Response> {
+    if ids.len() > MAX_BATCH_IDS {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())?);
+    }
+
+    let quotes = state
+        .get_quotes(&ids)
+        .await?
+        .into_iter()
+        .map(|quote| (quote.id(), quote))
+        .collect::<BTreeMap<_, _>>();
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string_pretty(&quotes)?))?)
+}
+
+async fn latest_by_author(
+    State(state): State<QuoteState>,
+    Path(author): Path<String>,
+) -> Result<Response> {
+    let quote = state.latest_by_author(&author).await?;
+
+    let Some(quote) = quote else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())?);
+    };
+
     Ok(Response::builder()
         .status(StatusCode::OK)
         .body(Body::from(serde_json::to_string_pretty(&quote)?))?)
 }
 
-async fn remove(State(state): State<QuoteState>, Path(id): Path<Uuid>) -> Result<Response> {
+async fn remove(State(state): State<QuoteState>, QuoteId(id): QuoteId) -> Result<Response> {
     let quote = state.delete_quote(id).await?;
 
     let Some(quote) = quote else {
@@ -59,17 +207,31 @@ async fn remove(State(state): State<QuoteState>, Path(id): Path<Uuid>) -> Result
         .body(Body::from(serde_json::to_string_pretty(&quote)?))?)
 }
 
+/// Missing quotes are reported before the body is looked at, so a malformed
+/// body for an unknown id is still a 404
 async fn undo(
     State(state): State<QuoteState>,
-    Path(id): Path<Uuid>,
-    Json(quote): Json<QuotePayload>,
+    QuoteId(id): QuoteId,
+    quote: std::result::Result<Json<QuotePayload>, JsonRejection>,
 ) -> Result<Response> {
-    let quote = state.update_quote(id, quote).await?;
-
-    let Some(quote) = quote else {
-        return Ok(Response::builder()
+    let not_found = || {
+        Response::builder()
             .status(StatusCode::NOT_FOUND)
-            .body(Body::empty())?);
+            .body(Body::empty())
+    };
+
+    if state.get_quote(id).await?.is_none() {
+        return Ok(not_found()?);
+    }
+
+    let Json(quote) = match quote {
+        Ok(quote) => quote,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    // the quote may have been removed while the body was being checked
+    let Some(quote) = state.update_quote(id, quote).await? else {
+        return Ok(not_found()?);
     };
 
     Ok(Response::builder()
@@ -82,45 +244,946 @@ async fn draft(
     State(state): State<QuoteState>,
     Json(quote): Json<QuotePayload>,
 ) -> Result<Response> {
+    if let Some(retry_after) = state.throttle_draft(&quote) {
+        return Ok(Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(RETRY_AFTER, retry_after.as_secs_f64().ceil().to_string())
+            .body(Body::empty())?);
+    }
+
     let quote = state.create_quote(quote).await?;
 
     Ok(Response::builder()
         .status(StatusCode::CREATED)
         .header(CONTENT_TYPE, "application/json")
+        .header(LOCATION, format!("/19/cite/{}", quote.id()))
         .body(Body::from(serde_json::to_string_pretty(&quote)?))?)
 }
 
-const PAGE_SIZE: i32 = 3;
-
 #[derive(Debug, Deserialize)]
 struct ListQuery {
     token: Option<String>,
+    #[serde(default)]
+    order: ListOrder,
+    /// Only quotes at this version or above, e.g. `2` for quotes edited at least once
+    min_version: Option<i32>,
+}
+
+/// `Last-Modified` uses the IMF-fixdate format HTTP dates require
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Whether `If-Modified-Since` is at or after `last_modified`, compared at the
+/// second precision HTTP dates carry
+fn not_modified(headers: &HeaderMap, last_modified: DateTime<Utc>) -> bool {
+    headers
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|since| since.to_str().ok())
+        .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+        .is_some_and(|since| last_modified.timestamp() <= since.timestamp())
 }
 
-async fn list(Query(query): Query<ListQuery>, State(state): State<QuoteState>) -> Result<Response> {
-    let mut current_page = 1;
-    if let Some(token) = query.token {
-        let Ok(Some(page_token)) = state.get_next_page_token(token) else {
+async fn list(
+    Query(query): Query<ListQuery>,
+    State(state): State<QuoteState>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let last_modified = state.last_modified().await?;
+    if let Some(last_modified) = last_modified {
+        if not_modified(&headers, last_modified) {
             return Ok(Response::builder()
-                .status(StatusCode::BAD_REQUEST)
+                .status(StatusCode::NOT_MODIFIED)
+                .header(
+                    LAST_MODIFIED,
+                    last_modified.format(HTTP_DATE_FORMAT).to_string(),
+                )
                 .body(Body::empty())?);
-        };
-
-        current_page = page_token;
+        }
     }
 
-    let current_offset = (current_page - 1) * PAGE_SIZE;
+    let paginator = state.paginator();
+    let Some(current_page) = paginator.page(query.token)? else {
+        return invalid_token();
+    };
 
-    let quotes = state.list_quotes(PAGE_SIZE + 1, current_offset).await?;
+    let min_version = query.min_version.unwrap_or(FIRST_VERSION);
+    let quotes = state
+        .list_quotes(
+            paginator.fetch_limit(),
+            paginator.offset(current_page),
+            query.order,
+            min_version,
+        )
+        .await?;
+    let total = state.count_quotes(min_version).await?;
+    let payload = list_page(&state, quotes, current_page, total)?;
 
-    let mut next_token = None;
-    if quotes.len() as i32 > PAGE_SIZE {
-        next_token = Some(state.create_next_page_token(current_page + 1)?);
+    let mut response = Response::builder().status(StatusCode::OK);
+    if let Some(last_modified) = last_modified {
+        response = response.header(
+            LAST_MODIFIED,
+            last_modified.format(HTTP_DATE_FORMAT).to_string(),
+        );
     }
+    Ok(response.body(Body::from(serde_json::to_string_pretty(&payload)?))?)
+}
+
+fn invalid_token() -> Result<Response> {
+    Ok(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::empty())?)
+}
+
+/// Wraps the quotes fetched for `current_page` into a page of the listing
+fn list_page(
+    state: &QuoteState,
+    quotes: Vec<Quote>,
+    current_page: i32,
+    total: i64,
+) -> Result<ListResponse> {
+    let paginator = state.paginator();
+    let (quotes, next_token) = paginator.paginate(quotes, current_page)?;
+    Ok(ListResponse::new(
+        quotes,
+        current_page,
+        next_token,
+        total,
+        paginator.page_size(),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct RecentQuery {
+    token: Option<String>,
+}
+
+/// Newest quotes first, so a feed never has to find the last page of `/list`
+async fn recent(
+    Query(query): Query<RecentQuery>,
+    State(state): State<QuoteState>,
+) -> Result<Response> {
+    let paginator = state.paginator();
+    let Some(current_page) = paginator.page(query.token)? else {
+        return invalid_token();
+    };
 
-    let payload = ListResponse::new(quotes, current_page, next_token);
+    let quotes = state
+        .list_recent(paginator.fetch_limit(), paginator.offset(current_page))
+        .await?;
+    let total = state.count_quotes(FIRST_VERSION).await?;
+    let payload = list_page(&state, quotes, current_page, total)?;
 
     Ok(Response::builder()
         .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
         .body(Body::from(serde_json::to_string_pretty(&payload)?))?)
 }
+
+const DEFAULT_AUTHORS_LIMIT: i32 = 50;
+const MAX_AUTHORS_LIMIT: i32 = 100;
+
+#[derive(Debug, Deserialize)]
+struct AuthorsQuery {
+    limit: Option<i32>,
+    offset: Option<i32>,
+}
+
+/// Quote counts per author, most prolific first, with the distinct author total
+async fn authors(
+    Query(query): Query<AuthorsQuery>,
+    State(state): State<QuoteState>,
+) -> Result<Response> {
+    let limit = query.limit.unwrap_or(DEFAULT_AUTHORS_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    if !(0..=MAX_AUTHORS_LIMIT).contains(&limit) || offset < 0 {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())?);
+    }
+
+    let authors = state.author_counts(limit, offset).await?;
+    let total = state.count_authors().await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string_pretty(
+            &serde_json::json!({ "authors": authors, "total": total }),
+        )?))?)
+}
+
+/// Most quotes a single search returns
+const SEARCH_LIMIT: i32 = 50;
+/// Most whitespace-separated terms a search may combine
+const MAX_SEARCH_TERMS: usize = 10;
+
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    q: String,
+    #[serde(default)]
+    mode: SearchMode,
+}
+
+/// Quotes whose text or author contains any, or with `mode=all` every, of the
+/// terms in `q`
+async fn search(
+    Query(query): Query<SearchQuery>,
+    State(state): State<QuoteState>,
+) -> Result<Response> {
+    let terms = query
+        .q
+        .split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if terms.is_empty() || terms.len() > MAX_SEARCH_TERMS {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())?);
+    }
+
+    let quotes = state
+        .search_quotes(&terms, query.mode, SEARCH_LIMIT)
+        .await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string_pretty(&quotes)?))?)
+}
+
+async fn stats(State(state): State<QuoteState>) -> Result<Response> {
+    let stats = state.quote_stats().await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&stats)?))?)
+}
+
+/// Number of quotes served in the Atom feed
+const FEED_SIZE: i32 = 10;
+
+async fn feed(State(state): State<QuoteState>) -> Result<Response> {
+    let quotes = state.latest_quotes(FEED_SIZE).await?;
+    let updated = quotes
+        .first()
+        .map(|quote| quote.created_at())
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339();
+
+    let entries = quotes
+        .iter()
+        .map(|quote| {
+            formatdoc! {r#"
+              <entry>
+                <id>urn:uuid:{id}</id>
+                <title>{title}</title>
+                <author><name>{author}</name></author>
+                <updated>{updated}</updated>
+                <content type="text">{title}</content>
+              </entry>
+            "#,
+              id = quote.id(),
+              title = encode_text(quote.quote()),
+              author = encode_text(quote.author()),
+              updated = quote.created_at().to_rfc3339(),
+            }
+        })
+        .collect::<String>();
+
+    let feed = formatdoc! {r#"
+      <?xml version="1.0" encoding="utf-8"?>
+      <feed xmlns="http://www.w3.org/2005/Atom">
+      <id>urn:cch24:quotes</id>
+      <title>Quotes</title>
+      <updated>{updated}</updated>
+      {entries}</feed>
+    "#};
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/atom+xml")
+        .body(Body::from(feed))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::http::{HeaderValue, Request};
+    use serde_json::Value;
+    use tower::{Service, ServiceExt};
+
+    use crate::utils::quote::in_memory::InMemoryQuoteStore;
+    use crate::utils::session_store::random_id;
+    use crate::utils::utf8_body::require_utf8;
+
+    fn test_routes(name: &str) -> RouterIntoService<Body> {
+        let dir = format!("cch24-day-nineteen-{name}-{}", random_id());
+        let persist = PersistInstance::new(std::env::temp_dir().join(dir)).unwrap();
+        router(QuoteState::new(
+            Box::new(InMemoryQuoteStore::default()),
+            persist,
+        ))
+    }
+
+    async fn send(
+        service: &mut RouterIntoService<Body>,
+        request: Request<Body>,
+    ) -> (StatusCode, String) {
+        let response = service.ready().await.unwrap().call(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn json_request(method: &str, uri: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    async fn draft_quote(service: &mut RouterIntoService<Body>, author: &str) -> Value {
+        let body = format!(r#"{{"author":"{author}","quote":"Ho ho ho"}}"#);
+        let (status, body) = send(service, json_request("POST", "/draft", &body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_draft_invalid_utf8_rejected_early() {
+        let mut service = require_utf8(test_routes("invalid-utf8"));
+        let request = Request::post("/draft")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                b"{\"author\":\"\xff\",\"quote\":\"Ho\"}".to_vec(),
+            ))
+            .unwrap();
+        let (status, body) = send(&mut service, request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, r#"{"error":"invalid_utf8"}"#);
+
+        let (status, _) = send(&mut service, get("/list")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(
+            &mut service,
+            json_request("POST", "/draft", r#"{"author":"Santa","quote":"Ho"}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_undo_missing_id_before_body() {
+        let mut service = test_routes("undo-missing");
+        let uri = format!("/undo/{}", Uuid::new_v4());
+        for body in [
+            "{",
+            r#"{"author":"Santa"}"#,
+            r#"{"author":"Santa","quote":"Ho"}"#,
+        ] {
+            let (status, _) = send(&mut service, json_request("PUT", &uri, body)).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_undo_existing_id_malformed_body() {
+        let mut service = test_routes("undo-malformed");
+        let quote = draft_quote(&mut service, "Santa").await;
+        let uri = format!("/undo/{}", quote["id"].as_str().unwrap());
+
+        let (status, _) = send(&mut service, json_request("PUT", &uri, "{")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(
+            &mut service,
+            json_request("PUT", &uri, r#"{"author":"Santa"}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = r#"{"author":"Santa","quote":"Ho ho"}"#;
+        let (status, body) = send(&mut service, json_request("PUT", &uri, body)).await;
+        assert_eq!(status, StatusCode::OK);
+        let quote: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(quote["version"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_cite_formats() {
+        let mut service = test_routes("cite-formats");
+        let body = r#"{"author":"Santa & co","quote":"Ho <ho> ho"}"#;
+        let (_, quote) = send(&mut service, json_request("POST", "/draft", body)).await;
+        let quote: Value = serde_json::from_str(&quote).unwrap();
+        let uri = format!("/cite/{}", quote["id"].as_str().unwrap());
+
+        for uri in [uri.clone(), format!("{uri}?format=json")] {
+            let (status, body) = send(&mut service, get(&uri)).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(serde_json::from_str::<Value>(&body).unwrap(), quote);
+        }
+
+        let (status, body) = send(&mut service, get(&format!("{uri}?format=markdown"))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "> Ho <ho> ho\n> — Santa & co");
+
+        let (status, body) = send(&mut service, get(&format!("{uri}?format=html"))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            "<blockquote><p>Ho &lt;ho&gt; ho</p><footer>— Santa &amp; co</footer></blockquote>"
+        );
+
+        let (status, _) = send(&mut service, get(&format!("{uri}?format=xml"))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_search_modes() {
+        let mut service = test_routes("search");
+        for (author, quote) in [
+            ("Ada Lovelace", "The engine weaves algebraic patterns"),
+            ("Ada", "Imagination is the discovering faculty"),
+            ("Charles Babbage", "Errors using inadequate data"),
+        ] {
+            let body = serde_json::json!({ "author": author, "quote": quote }).to_string();
+            send(&mut service, json_request("POST", "/draft", &body)).await;
+        }
+        let mut search = async |uri: &str| {
+            let (status, body) = send(&mut service, get(uri)).await;
+            assert_eq!(status, StatusCode::OK);
+            serde_json::from_str::<Value>(&body)
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|quote| quote["author"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            search("/search?q=ada+lovelace&mode=all").await,
+            ["Ada Lovelace"]
+        );
+        assert_eq!(
+            search("/search?q=ada+LOVELACE").await,
+            ["Ada Lovelace", "Ada"]
+        );
+        assert_eq!(
+            search("/search?q=lovelace+data&mode=any").await,
+            ["Ada Lovelace", "Charles Babbage"]
+        );
+        assert!(search("/search?q=ada+babbage&mode=all").await.is_empty());
+        assert!(search("/search?q=%25").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_without_terms() {
+        let mut service = test_routes("search-empty");
+        for uri in ["/search?q=", "/search?q=+++", "/search?q=ada&mode=some"] {
+            let (status, _) = send(&mut service, get(uri)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cite_invalid_id() {
+        let (status, body) = send(&mut test_routes("invalid-id"), get("/cite/not-a-uuid")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, r#"{"error":"invalid_id"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_draft_location() {
+        let body = r#"{"author":"Santa","quote":"Ho ho ho"}"#;
+        let response = test_routes("location")
+            .oneshot(json_request("POST", "/draft", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let location = response.headers()[LOCATION].to_str().unwrap().to_string();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let quote: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            location,
+            format!("/19/cite/{}", quote["id"].as_str().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quote_lifecycle() {
+        let mut service = test_routes("lifecycle");
+        let quote = draft_quote(&mut service, "Santa").await;
+        let id = quote["id"].as_str().unwrap();
+        assert_eq!(quote["version"], 1);
+
+        let (status, body) = send(&mut service, get(&format!("/cite/{id}"))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(serde_json::from_str::<Value>(&body).unwrap(), quote);
+
+        let undo = json_request(
+            "PUT",
+            &format!("/undo/{id}"),
+            r#"{"author":"Rudolph","quote":"Ho"}"#,
+        );
+        let (status, body) = send(&mut service, undo).await;
+        assert_eq!(status, StatusCode::OK);
+        let updated: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(updated["author"], "Rudolph");
+        assert_eq!(updated["version"], 2);
+
+        let remove = Request::delete(format!("/remove/{id}"))
+            .body(Body::empty())
+            .unwrap();
+        let (status, _) = send(&mut service, remove).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send(&mut service, get(&format!("/cite/{id}"))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_list_pagination() {
+        let mut service = test_routes("pagination");
+        for author in ["a", "b", "c", "d"] {
+            draft_quote(&mut service, author).await;
+        }
+
+        let (status, body) = send(&mut service, get("/list")).await;
+        assert_eq!(status, StatusCode::OK);
+        let page: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(page["page"], 1);
+        // the extra row fetched to detect a next page must not leak into this one
+        let authors = |page: &Value| {
+            page["quotes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|quote| quote["author"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(authors(&page), ["a", "b", "c"]);
+
+        let token = page["next_token"].as_str().unwrap();
+        let (status, body) = send(&mut service, get(&format!("/list?token={token}"))).await;
+        assert_eq!(status, StatusCode::OK);
+        let page: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(page["page"], 2);
+        assert_eq!(authors(&page), ["d"]);
+        assert_eq!(page["next_token"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_recent() {
+        let mut service = test_routes("recent");
+        for author in ["a", "b", "c", "d"] {
+            draft_quote(&mut service, author).await;
+        }
+
+        let (status, body) = send(&mut service, get("/recent")).await;
+        assert_eq!(status, StatusCode::OK);
+        let page: Value = serde_json::from_str(&body).unwrap();
+        let authors = |page: &Value| {
+            page["quotes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|quote| quote["author"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(authors(&page), ["d", "c", "b"]);
+        assert_eq!(page["total"], 4);
+
+        let token = page["next_token"].as_str().unwrap();
+        let (status, body) = send(&mut service, get(&format!("/recent?token={token}"))).await;
+        assert_eq!(status, StatusCode::OK);
+        let page: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(page["page"], 2);
+        assert_eq!(authors(&page), ["a"]);
+        assert_eq!(page["next_token"], Value::Null);
+
+        let (status, _) = send(&mut service, get(&format!("/recent?token={token}"))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    /// Checks every opened tag is closed in order
+    fn assert_well_formed(xml: &str) {
+        let mut open = vec![];
+        for tag in xml.split('<').skip(1) {
+            let tag = tag.split('>').next().unwrap();
+            if tag.starts_with('?') || tag.ends_with('/') {
+                continue;
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(open.pop(), Some(name.to_string()));
+            } else {
+                open.push(tag.split_whitespace().next().unwrap().to_string());
+            }
+        }
+        assert!(open.is_empty(), "unclosed tags: {open:?}");
+    }
+
+    #[tokio::test]
+    async fn test_feed() {
+        let mut service = test_routes("feed");
+        draft_quote(&mut service, "Santa").await;
+        let latest = draft_quote(&mut service, "Rudolph & co").await;
+
+        let (status, body) = send(&mut service, get("/feed.xml")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_well_formed(&body);
+        assert_eq!(body.matches("<entry>").count(), 2);
+
+        // the newest quote comes first
+        let first_entry = body.split("<entry>").nth(1).unwrap();
+        assert!(first_entry.contains(&format!("urn:uuid:{}", latest["id"].as_str().unwrap())));
+        assert!(first_entry.contains("<name>Rudolph &amp; co</name>"));
+    }
+
+    #[tokio::test]
+    async fn test_draft_rate_limited_per_author() {
+        let mut service = test_routes("draft-limit");
+        // the default limit is five drafts a minute
+        for _ in 0..5 {
+            draft_quote(&mut service, "Grinch").await;
+        }
+
+        let body = r#"{"author":"Grinch","quote":"Bah"}"#;
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(json_request("POST", "/draft", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "12");
+
+        draft_quote(&mut service, "Santa").await;
+    }
+
+    #[tokio::test]
+    async fn test_latest_by_author() {
+        let mut service = test_routes("latest-by-author");
+        draft_quote(&mut service, "Santa Claus").await;
+        let newer = draft_quote(&mut service, "Santa Claus").await;
+        draft_quote(&mut service, "Rudolph").await;
+
+        let (status, body) = send(&mut service, get("/latest/Santa%20Claus")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(serde_json::from_str::<Value>(&body).unwrap(), newer);
+
+        let (status, _) = send(&mut service, get("/latest/Grinch")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_list_total_pages_and_order() {
+        let mut service = test_routes("total-pages");
+        let (_, body) = send(&mut service, get("/list")).await;
+        let page: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(page["total"], 0);
+        assert_eq!(page["total_pages"], 0);
+
+        for author in ["a", "b", "c", "d", "e", "f", "g"] {
+            draft_quote(&mut service, author).await;
+        }
+
+        let (_, body) = send(&mut service, get("/list")).await;
+        let page: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(page["total"], 7);
+        assert_eq!(page["total_pages"], 3);
+        assert_eq!(page["quotes"][0]["author"], "a");
+
+        let (status, body) = send(&mut service, get("/list?order=newest")).await;
+        assert_eq!(status, StatusCode::OK);
+        let page: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(page["total_pages"], 3);
+        assert_eq!(page["quotes"][0]["author"], "g");
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let mut service = test_routes("stats");
+        let (status, body) = send(&mut service, get("/stats")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_str::<Value>(&body).unwrap(),
+            serde_json::json!({
+                "total": 0,
+                "total_words": 0,
+                "average_length": 0.0,
+                "longest_id": null,
+            })
+        );
+
+        let mut ids = vec![];
+        for quote in [
+            "Ho ho ho",
+            "Merry  Christmas to all",
+            "Ho ho ho ho ho ho ho",
+        ] {
+            let body = format!(r#"{{"author":"Santa","quote":"{quote}"}}"#);
+            let (status, body) = send(&mut service, json_request("POST", "/draft", &body)).await;
+            assert_eq!(status, StatusCode::CREATED);
+            ids.push(serde_json::from_str::<Value>(&body).unwrap()["id"].clone());
+        }
+
+        let (_, body) = send(&mut service, get("/stats")).await;
+        assert_eq!(
+            serde_json::from_str::<Value>(&body).unwrap(),
+            serde_json::json!({
+                "total": 3,
+                "total_words": 14,
+                "average_length": 17.0,
+                "longest_id": ids[1],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_min_version() {
+        let mut service = test_routes("min-version");
+        for author in ["a", "b", "c", "d", "e", "f"] {
+            let quote = draft_quote(&mut service, author).await;
+            if author == "a" || author == "c" {
+                continue;
+            }
+            let undo = json_request(
+                "PUT",
+                &format!("/undo/{}", quote["id"].as_str().unwrap()),
+                &format!(r#"{{"author":"{author}","quote":"Ho"}}"#),
+            );
+            let (status, _) = send(&mut service, undo).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let authors = |page: &Value| {
+            page["quotes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|quote| quote["author"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let (status, body) = send(&mut service, get("/list?min_version=2")).await;
+        assert_eq!(status, StatusCode::OK);
+        let page: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(page["total"], 4);
+        assert_eq!(authors(&page), ["b", "d", "e"]);
+
+        let token = page["next_token"].as_str().unwrap();
+        let uri = format!("/list?min_version=2&token={token}");
+        let (_, body) = send(&mut service, get(&uri)).await;
+        let page: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(authors(&page), ["f"]);
+        assert_eq!(page["next_token"], Value::Null);
+
+        let (_, body) = send(&mut service, get("/list")).await;
+        let page: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(page["total"], 6);
+    }
+
+    #[tokio::test]
+    async fn test_list_last_modified() {
+        let mut service = test_routes("last-modified");
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(get("/list"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(LAST_MODIFIED).is_none());
+
+        draft_quote(&mut service, "Santa").await;
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(get("/list"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let last_modified = response.headers()[LAST_MODIFIED].clone();
+
+        let cached = Request::get("/list")
+            .header(IF_MODIFIED_SINCE, last_modified.clone())
+            .body(Body::empty())
+            .unwrap();
+        let response = service.ready().await.unwrap().call(cached).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[LAST_MODIFIED], last_modified);
+
+        let stale = Request::get("/list")
+            .header(IF_MODIFIED_SINCE, "Sat, 01 Jan 2000 00:00:00 GMT")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = send(&mut service, stale).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(serde_json::from_str::<Value>(&body).unwrap()["total"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_list_last_modified_never_goes_back() {
+        let mut service = test_routes("last-modified-monotonic");
+        draft_quote(&mut service, "Santa").await;
+        let newest = draft_quote(&mut service, "Rudolph").await;
+
+        let (_, before) = list_last_modified(&mut service, None).await;
+
+        let remove = Request::delete(format!("/remove/{}", newest["id"].as_str().unwrap()))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(&mut service, remove).await.0, StatusCode::OK);
+        let (status, after_remove) = list_last_modified(&mut service, Some(before.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(parse_http_date(&after_remove) > parse_http_date(&before));
+
+        send(
+            &mut service,
+            Request::post("/reset").body(Body::empty()).unwrap(),
+        )
+        .await;
+        let (status, after_reset) =
+            list_last_modified(&mut service, Some(after_remove.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(parse_http_date(&after_reset) > parse_http_date(&after_remove));
+    }
+
+    /// Status and `Last-Modified` of `/list`, sent with `If-Modified-Since: since`
+    async fn list_last_modified(
+        service: &mut RouterIntoService<Body>,
+        since: Option<HeaderValue>,
+    ) -> (StatusCode, HeaderValue) {
+        let mut request = Request::get("/list");
+        if let Some(since) = since {
+            request = request.header(IF_MODIFIED_SINCE, since);
+        }
+        let request = request.body(Body::empty()).unwrap();
+        let response = service.ready().await.unwrap().call(request).await.unwrap();
+        (response.status(), response.headers()[LAST_MODIFIED].clone())
+    }
+
+    fn parse_http_date(date: &HeaderValue) -> DateTime<Utc> {
+        DateTime::parse_from_rfc2822(date.to_str().unwrap())
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn test_mutations_invalidate_cached_list() {
+        let mut service = test_routes("list-cache");
+        let authors = |body: &str| {
+            serde_json::from_str::<Value>(body).unwrap()["quotes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|quote| quote["author"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let (_, body) = send(&mut service, get("/list")).await;
+        assert!(authors(&body).is_empty());
+
+        let santa = draft_quote(&mut service, "Santa").await;
+        let (_, body) = send(&mut service, get("/list")).await;
+        assert_eq!(authors(&body), ["Santa"]);
+
+        let id = santa["id"].as_str().unwrap();
+        let undo = json_request(
+            "PUT",
+            &format!("/undo/{id}"),
+            r#"{"author":"Rudolph","quote":"Ho ho ho"}"#,
+        );
+        assert_eq!(send(&mut service, undo).await.0, StatusCode::OK);
+        let (_, body) = send(&mut service, get("/list")).await;
+        assert_eq!(authors(&body), ["Rudolph"]);
+
+        let remove = Request::delete(format!("/remove/{id}"))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(&mut service, remove).await.0, StatusCode::OK);
+        let (_, body) = send(&mut service, get("/list")).await;
+        assert!(authors(&body).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reset_tokens_keeps_quotes() {
+        let mut service = test_routes("reset-tokens");
+        for author in ["a", "b", "c", "d"] {
+            draft_quote(&mut service, author).await;
+        }
+        let (_, body) = send(&mut service, get("/list")).await;
+        let page: Value = serde_json::from_str(&body).unwrap();
+        let token = page["next_token"].as_str().unwrap().to_string();
+
+        let reset = Request::post("/reset-tokens").body(Body::empty()).unwrap();
+        let (status, _) = send(&mut service, reset).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send(&mut service, get(&format!("/list?token={token}"))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (_, body) = send(&mut service, get("/list")).await;
+        let page: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(page["total"], 4);
+    }
+
+    #[tokio::test]
+    async fn test_cite_batch() {
+        let mut service = test_routes("cite-batch");
+        let santa = draft_quote(&mut service, "Santa").await;
+        let rudolph = draft_quote(&mut service, "Rudolph").await;
+        draft_quote(&mut service, "Grinch").await;
+        let missing = Uuid::new_v4();
+
+        let ids = serde_json::json!([santa["id"], missing, rudolph["id"]]).to_string();
+        let (status, body) = send(&mut service, json_request("POST", "/cite/batch", &ids)).await;
+        assert_eq!(status, StatusCode::OK);
+        let quotes: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(quotes.as_object().unwrap().len(), 2);
+        assert_eq!(quotes[santa["id"].as_str().unwrap()], santa);
+        assert_eq!(quotes[rudolph["id"].as_str().unwrap()], rudolph);
+
+        let too_many = serde_json::to_string(&vec![missing; MAX_BATCH_IDS + 1]).unwrap();
+        let (status, _) = send(&mut service, json_request("POST", "/cite/batch", &too_many)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_authors_pagination() {
+        let mut service = test_routes("authors");
+        for author in [
+            "Santa", "Rudolph", "Santa", "Dasher", "Santa", "Rudolph", "Vixen",
+        ] {
+            draft_quote(&mut service, author).await;
+        }
+
+        let (status, body) = send(&mut service, get("/authors?limit=2&offset=1")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_str::<Value>(&body).unwrap(),
+            serde_json::json!({
+                "authors": [
+                    {"author": "Rudolph", "count": 2},
+                    {"author": "Dasher", "count": 1},
+                ],
+                "total": 4,
+            })
+        );
+
+        let (status, _) = send(&mut service, get("/authors?limit=1000")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
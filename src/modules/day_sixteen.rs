@@ -1,38 +1,163 @@
+use std::sync::Arc;
+
+use anyhow::Context;
 use axum::body::Body;
-use axum::http::header::CONTENT_TYPE;
-use axum::http::StatusCode;
-use axum::response::Response;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post, RouterIntoService};
 use axum::{Json, Router};
 
-use axum_extra::extract::{cookie::Cookie, CookieJar};
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum_extra::extract::CookieJar;
 use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
 use serde_json::Value;
 
-pub fn routes() -> RouterIntoService<Body> {
+use crate::utils::extractors::AcceptsJson;
+
+/// Environment variable holding Santa's public key as a PEM string
+pub const SANTA_PEM_ENV: &str = "SANTA_PEM";
+/// Environment variable holding a path to Santa's public key PEM file
+pub const SANTA_PEM_PATH_ENV: &str = "SANTA_PEM_PATH";
+/// Environment variable naming the cookie `/wrap` stores the gift token in
+pub const JWT_COOKIE_NAME_ENV: &str = "JWT_COOKIE_NAME";
+
+const DEFAULT_JWT_COOKIE_NAME: &str = "gift";
+
+/// Environment variable capping how deeply `/wrap` claims may nest
+pub const MAX_CLAIMS_DEPTH_ENV: &str = "JWT_MAX_CLAIMS_DEPTH";
+
+const DEFAULT_MAX_CLAIMS_DEPTH: usize = 32;
+
+/// Environment variable listing the algorithms `/decode` accepts, comma-separated
+pub const DECODE_ALGORITHMS_ENV: &str = "JWT_DECODE_ALGS";
+
+const DEFAULT_DECODE_ALGORITHMS: [Algorithm; 2] = [Algorithm::RS256, Algorithm::RS512];
+
+/// Largest claims body accepted by `/wrap`, bigger bodies are rejected with 413
+const MAX_CLAIMS_BYTES: usize = 64 * 1024;
+/// Longest token accepted by `/decode`
+const MAX_TOKEN_LEN: usize = 8 * 1024;
+
+pub fn routes() -> anyhow::Result<RouterIntoService<Body>> {
+    Ok(router(JwtState {
+        decoding_key: santa_decoding_key(&santa_pem()?)?,
+        cookie_name: jwt_cookie_name(),
+        max_claims_depth: max_claims_depth(),
+        decode_algorithms: decode_algorithms()?,
+    }))
+}
+
+struct JwtState {
+    /// Santa's public key, used by `/decode`
+    decoding_key: DecodingKey,
+    /// Cookie shared by `/wrap` and `/unwrap`
+    cookie_name: String,
+    /// Deepest nesting of arrays and objects `/wrap` signs
+    max_claims_depth: usize,
+    /// Algorithms `/decode` accepts tokens signed with
+    decode_algorithms: Vec<Algorithm>,
+}
+
+pub fn jwt_cookie_name() -> String {
+    std::env::var(JWT_COOKIE_NAME_ENV)
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| DEFAULT_JWT_COOKIE_NAME.to_string())
+}
+
+pub fn max_claims_depth() -> usize {
+    std::env::var(MAX_CLAIMS_DEPTH_ENV)
+        .ok()
+        .and_then(|depth| depth.parse().ok())
+        .unwrap_or(DEFAULT_MAX_CLAIMS_DEPTH)
+}
+
+pub fn decode_algorithms() -> anyhow::Result<Vec<Algorithm>> {
+    match std::env::var(DECODE_ALGORITHMS_ENV) {
+        Ok(algorithms) if !algorithms.trim().is_empty() => parse_algorithms(&algorithms)
+            .with_context(|| format!("invalid {DECODE_ALGORITHMS_ENV} {algorithms:?}")),
+        _ => Ok(DEFAULT_DECODE_ALGORITHMS.to_vec()),
+    }
+}
+
+/// Parses a list like `RS256,PS256`, only allowing algorithms Santa's RSA key
+/// can verify
+fn parse_algorithms(algorithms: &str) -> anyhow::Result<Vec<Algorithm>> {
+    algorithms
+        .split(',')
+        .map(|algorithm| {
+            let algorithm = algorithm.trim().parse::<Algorithm>()?;
+            match algorithm {
+                Algorithm::RS256
+                | Algorithm::RS384
+                | Algorithm::RS512
+                | Algorithm::PS256
+                | Algorithm::PS384
+                | Algorithm::PS512 => Ok(algorithm),
+                _ => anyhow::bail!("{algorithm:?} is not an RSA algorithm"),
+            }
+        })
+        .collect()
+}
+
+/// Nesting depth of arrays and objects, 0 for scalars
+fn depth(value: &Value) -> usize {
+    let children = match value {
+        Value::Array(values) => values.iter().map(depth).max(),
+        Value::Object(values) => values.values().map(depth).max(),
+        _ => return 0,
+    };
+    1 + children.unwrap_or(0)
+}
+
+fn router(state: JwtState) -> RouterIntoService<Body> {
     Router::new()
-        .route("/wrap", post(wrap))
+        .route(
+            "/wrap",
+            post(wrap).layer(DefaultBodyLimit::max(MAX_CLAIMS_BYTES)),
+        )
         .route("/unwrap", get(unwrap))
         .route("/decode", post(decode))
+        .route("/decode/full", post(decode_full))
+        .with_state(Arc::new(state))
         .into_service()
 }
 
 // The secret is not private, as this is just a code-hunt...
 const JWT_SECRET: &str = "SUPER_SECRET_KEY";
 
-async fn wrap(jar: CookieJar, Json(claims): Json<Value>) -> (StatusCode, CookieJar) {
+async fn wrap(
+    State(state): State<Arc<JwtState>>,
+    AcceptsJson(accepts_json): AcceptsJson,
+    jar: CookieJar,
+    Json(claims): Json<Value>,
+) -> Response {
+    if depth(&claims) > state.max_claims_depth {
+        return (StatusCode::BAD_REQUEST, jar).into_response();
+    }
+
     let Ok(jwt) = jsonwebtoken::encode(
         &Header::default(),
         &claims,
         &EncodingKey::from_secret(JWT_SECRET.as_ref()),
     ) else {
-        return (StatusCode::BAD_REQUEST, jar);
+        return (StatusCode::BAD_REQUEST, jar).into_response();
     };
-    (StatusCode::OK, jar.add(Cookie::new("gift", jwt)))
+    let cookie = Cookie::build((state.cookie_name.clone(), jwt.clone()))
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict);
+    if accepts_json {
+        let body = Json(serde_json::json!({ "token": jwt }));
+        return (StatusCode::OK, jar.add(cookie), body).into_response();
+    }
+    (StatusCode::OK, jar.add(cookie)).into_response()
 }
 
-async fn unwrap(jar: CookieJar) -> Response {
-    let Some(jwt) = jar.get("gift") else {
+async fn unwrap(State(state): State<Arc<JwtState>>, jar: CookieJar) -> Response {
+    let Some(jwt) = jar.get(&state.cookie_name) else {
         return Response::builder()
             .status(StatusCode::BAD_REQUEST)
             .body(Body::empty())
@@ -62,17 +187,79 @@ async fn unwrap(jar: CookieJar) -> Response {
 
 const SANTA_PEM: &str = include_str!("../../resources/santa.pem");
 
-async fn decode(jwt: String) -> Response {
-    let decoding_key = DecodingKey::from_rsa_pem(SANTA_PEM.trim().as_ref()).unwrap();
+/// Santa's public key from the environment, falling back to the embedded one
+fn santa_pem() -> anyhow::Result<String> {
+    if let Ok(pem) = std::env::var(SANTA_PEM_ENV) {
+        return Ok(pem);
+    }
+    if let Ok(path) = std::env::var(SANTA_PEM_PATH_ENV) {
+        return std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {SANTA_PEM_PATH_ENV} file {path}"));
+    }
+    Ok(SANTA_PEM.to_string())
+}
+
+fn santa_decoding_key(pem: &str) -> anyhow::Result<DecodingKey> {
+    DecodingKey::from_rsa_pem(pem.trim().as_ref()).context("malformed Santa RSA public key")
+}
+
+/// Token from an `Authorization: Bearer` header, falling back to the raw body
+fn token_from(headers: &HeaderMap, body: String) -> Option<String> {
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty());
+    bearer.or_else(|| Some(body).filter(|body| !body.is_empty()))
+}
+
+async fn decode(State(state): State<Arc<JwtState>>, headers: HeaderMap, body: String) -> Response {
+    verified_response(&state, &headers, body, |TokenData { claims, .. }| claims)
+}
+
+/// Like `/decode`, alongside the token's header
+async fn decode_full(
+    State(state): State<Arc<JwtState>>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    verified_response(&state, &headers, body, |TokenData { header, claims }| {
+        let mut header = serde_json::to_value(header).unwrap_or_default();
+        // unset fields are left out when serializing, but callers look for these
+        if let Value::Object(fields) = &mut header {
+            for field in ["kid", "typ"] {
+                fields.entry(field).or_insert(Value::Null);
+            }
+        }
+        serde_json::json!({ "header": header, "claims": claims })
+    })
+}
+
+/// Verifies the request's token with Santa's key, rendering it with `render`
+/// on success; bad signatures are a 401, any other problem a 400
+fn verified_response(
+    state: &JwtState,
+    headers: &HeaderMap,
+    body: String,
+    render: impl FnOnce(TokenData<Value>) -> Value,
+) -> Response {
+    let Some(jwt) = token_from(headers, body).filter(|jwt| jwt.len() <= MAX_TOKEN_LEN) else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())
+            .unwrap();
+    };
+
     let mut validation = Validation::new(Algorithm::RS256);
     validation.required_spec_claims.clear();
-    validation.algorithms = vec![Algorithm::RS256, Algorithm::RS512];
+    validation.algorithms = state.decode_algorithms.clone();
 
-    match jsonwebtoken::decode::<Value>(&jwt, &decoding_key, &validation) {
-        Ok(TokenData { claims, .. }) => Response::builder()
+    match jsonwebtoken::decode::<Value>(&jwt, &state.decoding_key, &validation) {
+        Ok(token) => Response::builder()
             .status(StatusCode::OK)
             .header(CONTENT_TYPE, "application/json")
-            .body(Body::from(claims.to_string()))
+            .body(Body::from(render(token).to_string()))
             .unwrap(),
         Err(error) => match error.kind() {
             jsonwebtoken::errors::ErrorKind::InvalidSignature => Response::builder()
@@ -86,3 +273,270 @@ async fn decode(jwt: String) -> Response {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::http::header::{ACCEPT, COOKIE, SET_COOKIE};
+    use axum::http::Request;
+    use serde_json::json;
+    use tower::{Service, ServiceExt};
+
+    const TEST_PRIVATE_PEM: &str = include_str!("../../resources/test/rsa_private.pem");
+    const TEST_PUBLIC_PEM: &str = include_str!("../../resources/test/rsa_public.pem");
+
+    fn signed_token() -> String {
+        signed_token_with(json!({"gift": "coal"}))
+    }
+
+    fn signed_token_with(claims: Value) -> String {
+        signed_token_using(Algorithm::RS256, claims)
+    }
+
+    fn signed_token_using(algorithm: Algorithm, claims: Value) -> String {
+        jsonwebtoken::encode(
+            &Header::new(algorithm),
+            &claims,
+            &EncodingKey::from_rsa_pem(TEST_PRIVATE_PEM.as_bytes()).unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn test_router(pem: &str) -> RouterIntoService<Body> {
+        router(JwtState {
+            decoding_key: santa_decoding_key(pem).unwrap(),
+            cookie_name: DEFAULT_JWT_COOKIE_NAME.to_string(),
+            max_claims_depth: DEFAULT_MAX_CLAIMS_DEPTH,
+            decode_algorithms: DEFAULT_DECODE_ALGORITHMS.to_vec(),
+        })
+    }
+
+    async fn post_decode(router: RouterIntoService<Body>, jwt: String) -> (StatusCode, String) {
+        let request = Request::post("/decode").body(Body::from(jwt)).unwrap();
+        send(router, request).await
+    }
+
+    async fn send(router: RouterIntoService<Body>, request: Request<Body>) -> (StatusCode, String) {
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_decode_with_runtime_key() {
+        let router = test_router(TEST_PUBLIC_PEM);
+        let (status, body) = post_decode(router, signed_token()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"gift":"coal"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_decode_rejects_other_key() {
+        let router = test_router(SANTA_PEM);
+        let (status, _) = post_decode(router, signed_token()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_decode_from_authorization_header() {
+        let router = test_router(TEST_PUBLIC_PEM);
+        let request = Request::post("/decode")
+            .header(AUTHORIZATION, format!("Bearer {}", signed_token()))
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = send(router, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"gift":"coal"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_decode_full() {
+        let request = Request::post("/decode/full")
+            .body(Body::from(signed_token()))
+            .unwrap();
+        let (status, body) = send(test_router(TEST_PUBLIC_PEM), request).await;
+        assert_eq!(status, StatusCode::OK);
+        let token: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(token["header"]["alg"], "RS256");
+        assert_eq!(token["header"]["typ"], "JWT");
+        assert_eq!(token["header"]["kid"], Value::Null);
+        assert_eq!(token["claims"], json!({"gift": "coal"}));
+
+        let request = Request::post("/decode/full")
+            .body(Body::from(signed_token()))
+            .unwrap();
+        let (status, _) = send(test_router(SANTA_PEM), request).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let request = Request::post("/decode/full")
+            .body(Body::from("not.a.jwt"))
+            .unwrap();
+        let (status, _) = send(test_router(TEST_PUBLIC_PEM), request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_decode_without_token() {
+        let router = test_router(TEST_PUBLIC_PEM);
+        let (status, _) = post_decode(router, String::new()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_wrap_oversized_claims() {
+        let router = test_router(SANTA_PEM);
+        let claims =
+            serde_json::to_string(&json!({"gift": "🎁".repeat(MAX_CLAIMS_BYTES)})).unwrap();
+        let request = Request::post("/wrap")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(claims))
+            .unwrap();
+        let (status, _) = send(router, request).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_decode_oversized_token() {
+        let router = test_router(TEST_PUBLIC_PEM);
+        // validly signed, so only the length cap rejects it
+        let jwt = signed_token_with(json!({"gift": "coal".repeat(MAX_TOKEN_LEN)}));
+        let (status, _) = post_decode(router, jwt).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_parse_algorithms() {
+        assert_eq!(
+            parse_algorithms("RS256, PS512").unwrap(),
+            vec![Algorithm::RS256, Algorithm::PS512]
+        );
+        assert!(parse_algorithms("RS256,HS256").is_err());
+        assert!(parse_algorithms("none").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decode_disallowed_algorithm() {
+        let jwt = signed_token_using(Algorithm::RS512, json!({"gift": "coal"}));
+        let (status, _) = post_decode(test_router(TEST_PUBLIC_PEM), jwt.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let router = router(JwtState {
+            decoding_key: santa_decoding_key(TEST_PUBLIC_PEM).unwrap(),
+            cookie_name: DEFAULT_JWT_COOKIE_NAME.to_string(),
+            max_claims_depth: DEFAULT_MAX_CLAIMS_DEPTH,
+            decode_algorithms: vec![Algorithm::RS256],
+        });
+        let (status, _) = post_decode(router, jwt).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_malformed_key() {
+        assert!(santa_decoding_key("not a pem").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wrap_cookie_name_and_attributes() {
+        let mut service = router(JwtState {
+            decoding_key: santa_decoding_key(SANTA_PEM).unwrap(),
+            cookie_name: "present".to_string(),
+            max_claims_depth: DEFAULT_MAX_CLAIMS_DEPTH,
+            decode_algorithms: DEFAULT_DECODE_ALGORITHMS.to_vec(),
+        });
+        let request = Request::post("/wrap")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"gift":"coal"}"#))
+            .unwrap();
+        let response = service.ready().await.unwrap().call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let set_cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+        let cookie = Cookie::parse(set_cookie.to_string()).unwrap();
+        assert_eq!(cookie.name(), "present");
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Strict));
+
+        let request = Request::get("/unwrap")
+            .header(COOKIE, format!("present={}", cookie.value()))
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = send(service, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"gift":"coal"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_wrap_token_body() {
+        let request = Request::post("/wrap")
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, "application/json")
+            .body(Body::from(r#"{"gift":"coal"}"#))
+            .unwrap();
+        let response = test_router(SANTA_PEM).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        let cookie = Cookie::parse(response.headers()[SET_COOKIE].to_str().unwrap().to_string())
+            .unwrap()
+            .value()
+            .to_string();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, json!({ "token": cookie }));
+    }
+
+    #[tokio::test]
+    async fn test_wrap_cookie_only_by_default() {
+        let request = Request::post("/wrap")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"gift":"coal"}"#))
+            .unwrap();
+        let response = test_router(SANTA_PEM).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(SET_COOKIE));
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+    }
+
+    fn nested_claims(depth: usize) -> String {
+        format!(
+            "{}{}",
+            r#"{"gift":"#.repeat(depth),
+            "1".to_string() + &"}".repeat(depth)
+        )
+    }
+
+    async fn post_wrap(claims: String) -> StatusCode {
+        let request = Request::post("/wrap")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(claims))
+            .unwrap();
+        send(test_router(SANTA_PEM), request).await.0
+    }
+
+    #[tokio::test]
+    async fn test_wrap_claims_at_depth_limit() {
+        let claims = nested_claims(DEFAULT_MAX_CLAIMS_DEPTH);
+        assert_eq!(
+            depth(&serde_json::from_str(&claims).unwrap()),
+            DEFAULT_MAX_CLAIMS_DEPTH
+        );
+        assert_eq!(post_wrap(claims).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_wrap_claims_too_deep() {
+        let claims = nested_claims(DEFAULT_MAX_CLAIMS_DEPTH + 1);
+        assert_eq!(post_wrap(claims).await, StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,77 +1,531 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::body::Body;
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::http::header::CONTENT_TYPE;
 use axum::http::StatusCode;
 use axum::response::Response;
 use axum::routing::{get, post, RouterIntoService};
 use axum::Router;
 use rand::rngs::StdRng;
 use rand::SeedableRng;
-use tokio::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use shuttle_persist::PersistInstance;
+use tokio::sync::{Mutex, RwLock};
 
-use crate::utils::connect_four::{Connect4, Player, BOARD_SIZE};
+use crate::utils::connect_four::{
+    BoardView, Connect4, Difficulty, Flip, Player, BOARD_SIZE, MAX_CELLS,
+};
 use crate::utils::error_handling::Result;
+use crate::utils::extractors::AcceptsJson;
+use crate::utils::game_history::GameHistory;
+use crate::utils::session_store::{random_id, SessionStore};
 
-pub fn routes() -> RouterIntoService<Body> {
+/// Environment variable capping the number of concurrent game sessions
+pub const MAX_GAMES_ENV: &str = "MAX_GAMES";
+
+const DEFAULT_MAX_GAMES: usize = 1000;
+
+/// How long a game has to sit unused before it may be evicted for a new one
+pub const GAME_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Environment variables sizing every board, both `BOARD_SIZE` by default
+pub const BOARD_ROWS_ENV: &str = "CONNECT4_ROWS";
+pub const BOARD_COLS_ENV: &str = "CONNECT4_COLS";
+
+pub fn routes(persist: PersistInstance) -> RouterIntoService<Body> {
+    router(RouterState::new(persist, board_size())).into_service()
+}
+
+fn router(state: RouterState) -> Router {
     Router::new()
         .route("/board", get(board))
+        .route("/board.json", get(board_json))
+        .route("/board.svg", get(board_svg))
+        .route("/config", get(config))
         .route("/reset", post(reset))
         .route("/place/:player/:column", post(place))
+        .route("/fill/:player/:column/:count", post(fill))
         .route("/random-board", get(random_board))
-        .with_state(RouterState::new())
-        .into_service()
+        .route("/history", get(history))
+        .route("/moves", get(moves))
+        .route("/undo", post(undo))
+        .route("/rewind/:n", post(rewind))
+        .route("/analyze/:player", get(analyze))
+        .route("/can-place/:column", get(can_place))
+        .route("/hint/:player", get(hint))
+        .route("/ai/:player", post(ai))
+        .route("/games", post(create_game))
+        .route("/:game_id/board", get(game_board))
+        .route("/:game_id/reset", post(game_reset))
+        .route("/:game_id/place/:player/:column", post(game_place))
+        .route("/:game_id/share", post(game_share))
+        .route("/spectate/:token", get(spectate))
+        .with_state(state)
 }
 
-async fn board(State(state): State<RouterState>) -> Result<Response> {
-    let state = state.0.read().await;
+fn board_response(game: &Connect4) -> Result<Response> {
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .body(state.game_state.to_string().into())?)
+        .body(game.to_string().into())?)
+}
+
+fn not_found() -> Result<Response> {
+    Ok(Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::empty())?)
 }
 
-async fn reset(State(state): State<RouterState>) -> Result<Response> {
-    let mut state = state.0.write().await;
-    state.game_state.reset();
-    state.rng = StdRng::seed_from_u64(2024);
+#[derive(Debug, Deserialize)]
+struct BoardQuery {
+    #[serde(default)]
+    flip: Flip,
+}
+
+async fn board(
+    State(state): State<RouterState>,
+    Query(query): Query<BoardQuery>,
+) -> Result<Response> {
+    let state = state.game.read().await;
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .body(state.game_state.to_string().into())?)
+        .body(state.game_state.flipped(query.flip).to_string().into())?)
+}
+
+/// How empty cells appear in `/board.json`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum EmptyCells {
+    #[default]
+    Null,
+    /// The string `"empty"`, for frontends that can't tell `null` apart
+    String,
+}
+
+/// Stands in for empty cells with `?empty=string`
+const EMPTY_CELL: &str = "empty";
+
+#[derive(Debug, Deserialize)]
+struct BoardJsonQuery {
+    #[serde(default)]
+    empty: EmptyCells,
+}
+
+async fn board_json(
+    State(state): State<RouterState>,
+    Query(query): Query<BoardJsonQuery>,
+) -> Result<Response> {
+    let state = state.game.read().await;
+    let mut board = serde_json::to_value(BoardView::from(&state.game_state))?;
+    if query.empty == EmptyCells::String {
+        let cells = board["cells"].as_array_mut().into_iter().flatten();
+        for cell in cells.filter_map(|row| row.as_array_mut()).flatten() {
+            if cell.is_null() {
+                *cell = EMPTY_CELL.into();
+            }
+        }
+    }
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(board.to_string().into())?)
+}
+
+async fn board_svg(State(state): State<RouterState>) -> Result<Response> {
+    let state = state.game.read().await;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "image/svg+xml")
+        .body(state.game_state.to_svg().into())?)
+}
+
+/// Text boards are always drawn with emoji
+const BOARD_THEME: &str = "emoji";
+
+/// Board shape and rules clients need to render the game
+#[derive(Debug, Serialize)]
+struct BoardConfig {
+    rows: usize,
+    cols: usize,
+    win_length: Option<usize>,
+    theme: &'static str,
+}
+
+async fn config(State(state): State<RouterState>) -> Result<Response> {
+    let state = state.game.read().await;
+    let config = BoardConfig {
+        rows: state.game_state.rows(),
+        cols: state.game_state.cols(),
+        win_length: state.game_state.win_length(),
+        theme: BOARD_THEME,
+    };
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(&config)?.into())?)
+}
+
+/// Resets the game, answering JSON clients with whether there was anything to clear
+async fn reset(
+    State(state): State<RouterState>,
+    AcceptsJson(accepts_json): AcceptsJson,
+) -> Result<Response> {
+    let mut state = state.game.write().await;
+    let was_reset = state.game_state.move_count() > 0;
+    state.reset();
+    if accepts_json {
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/json")
+            .body(
+                serde_json::json!({ "was_reset": was_reset })
+                    .to_string()
+                    .into(),
+            )?);
+    }
+    board_response(&state.game_state)
 }
 
 async fn place(
     State(state): State<RouterState>,
     Path((player, column)): Path<(Player, usize)>,
+    AcceptsJson(accepts_json): AcceptsJson,
 ) -> Result<Response> {
-    let mut state = state.0.write().await;
+    let mut game = state.game.write().await;
+    game.place(player, column, &state.history, accepts_json)
+        .await
+}
 
-    if !(1..=BOARD_SIZE).contains(&column) {
+/// Drops up to `count` of `player`'s pieces into the 1-based `column`,
+/// stopping early once the column is full or the game is over
+async fn fill(
+    State(state): State<RouterState>,
+    Path((player, column, count)): Path<(Player, usize, usize)>,
+) -> Result<Response> {
+    let mut game = state.game.write().await;
+    if !(1..=game.game_state.cols()).contains(&column) {
         return Ok(Response::builder()
             .status(StatusCode::BAD_REQUEST)
             .body(Body::empty())?);
     }
 
-    if state.game_state.column_full(column - 1)
-        || state.game_state.board_full()
-        || state.game_state.winner().is_some()
-    {
-        return Ok(Response::builder()
-            .status(StatusCode::SERVICE_UNAVAILABLE)
-            .body(state.game_state.to_string().into())?);
+    let mut placed = 0;
+    while placed < count && Legality::of(&game.game_state, column - 1) == Legality::Ok {
+        game.game_state.play(player, column - 1)?;
+        placed += 1;
+    }
+    // a game that was already over has been recorded before
+    if placed > 0 {
+        state.history.record_if_finished(&game.game_state).await?;
     }
 
-    state.game_state.play(player, column - 1)?;
+    let game_over = game.game_state.winner().is_some() || game.game_state.board_full();
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .body(state.game_state.to_string().into())?)
+        .header(CONTENT_TYPE, "application/json")
+        .body(
+            serde_json::json!({ "placed": placed, "game_over": game_over })
+                .to_string()
+                .into(),
+        )?)
 }
 
 async fn random_board(State(state): State<RouterState>) -> Result<Response> {
-    let mut state = state.0.write().await;
-    let random_board = Connect4::random(&mut state.rng);
+    let mut state = state.game.write().await;
+    let (rows, cols) = (state.game_state.rows(), state.game_state.cols());
+    let random_board = Connect4::random(rows, cols, &mut state.rng);
+    board_response(&random_board)
+}
+
+/// Minimax evaluation of every column, `best`/`worst` being 1-based columns
+#[derive(Debug, Serialize)]
+struct Analysis {
+    scores: Vec<Option<i32>>,
+    best: Option<usize>,
+    worst: Option<usize>,
+}
+
+impl Analysis {
+    fn new(scores: Vec<Option<i32>>) -> Self {
+        let mut best: Option<(usize, i32)> = None;
+        let mut worst: Option<(usize, i32)> = None;
+        // strict comparisons keep the lowest column on ties
+        for (column, score) in scores.iter().enumerate() {
+            let Some(score) = *score else {
+                continue;
+            };
+            if best.is_none_or(|(_, best)| score > best) {
+                best = Some((column + 1, score));
+            }
+            if worst.is_none_or(|(_, worst)| score < worst) {
+                worst = Some((column + 1, score));
+            }
+        }
+        Self {
+            scores,
+            best: best.map(|(column, _)| column),
+            worst: worst.map(|(column, _)| column),
+        }
+    }
+}
+
+/// Runs a game tree search on a copy of the board on the blocking pool, so
+/// neither the game lock nor an async worker is held while it runs
+async fn search<T: Send + 'static>(
+    game: Connect4,
+    search: impl FnOnce(&Connect4) -> T + Send + 'static,
+) -> Result<T> {
+    Ok(tokio::task::spawn_blocking(move || search(&game)).await?)
+}
+
+async fn analyze(State(state): State<RouterState>, Path(player): Path<Player>) -> Result<Response> {
+    let game = state.game.read().await.game_state.clone();
+    let scores = search(game, move |game| {
+        game.evaluate_columns(player, game.search_depth())
+    })
+    .await?;
+    let analysis = Analysis::new(scores);
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(&analysis)?.into())?)
+}
+
+#[derive(Debug, Deserialize)]
+struct HintQuery {
+    #[serde(default)]
+    difficulty: Difficulty,
+}
+
+/// Suggested 1-based column for `player`, `null` once nothing can be played
+async fn hint(
+    State(state): State<RouterState>,
+    Path(player): Path<Player>,
+    Query(query): Query<HintQuery>,
+) -> Result<Response> {
+    let game = state.game.read().await.game_state.clone();
+    // own rng so hints don't shift the seeded `/random-board` sequence
+    let column = search(game, move |game| {
+        game.best_move(player, query.difficulty, &mut rand::thread_rng())
+    })
+    .await?
+    .map(|column| column + 1);
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "column": column }).to_string().into())?)
+}
+
+/// Plays the move a hard search picks for `player`, answering like `place`, or
+/// 409 when the board changed while searching
+async fn ai(
+    State(state): State<RouterState>,
+    Path(player): Path<Player>,
+    AcceptsJson(accepts_json): AcceptsJson,
+) -> Result<Response> {
+    let snapshot = state.game.read().await.game_state.clone();
+    // hard searches don't draw from the rng
+    let column = search(snapshot.clone(), move |game| {
+        game.best_move(player, Difficulty::Hard, &mut rand::thread_rng())
+    })
+    .await?;
+
+    let mut game = state.game.write().await;
+    if game.game_state != snapshot {
+        return Ok(Response::builder()
+            .status(StatusCode::CONFLICT)
+            .body(Body::empty())?);
+    }
+    // no move only happens once the game is over, which `place` turns into a 503
+    let column = column.map_or(1, |column| column + 1);
+    game.place(player, column, &state.history, accepts_json)
+        .await
+}
+
+/// Why a piece can or cannot be dropped into a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Legality {
+    Ok,
+    Full,
+    GameOver,
+}
+
+impl Legality {
+    /// Checks the 0-based `column` of `game`
+    fn of(game: &Connect4, column: usize) -> Self {
+        if game.winner().is_some() || game.board_full() {
+            Self::GameOver
+        } else if game.column_full(column) {
+            Self::Full
+        } else {
+            Self::Ok
+        }
+    }
+}
+
+async fn can_place(
+    State(state): State<RouterState>,
+    Path(column): Path<usize>,
+) -> Result<Response> {
+    let state = state.game.read().await;
+    if !(1..=state.game_state.cols()).contains(&column) {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())?);
+    }
+
+    let reason = Legality::of(&state.game_state, column - 1);
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(
+            serde_json::json!({ "legal": reason == Legality::Ok, "reason": reason })
+                .to_string()
+                .into(),
+        )?)
+}
+
+async fn history(State(state): State<RouterState>) -> Result<Response> {
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .body(random_board.to_string().into())?)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(&state.history.list().await)?.into())?)
+}
+
+/// Takes back the last move on the main board
+async fn undo(State(state): State<RouterState>) -> Result<Response> {
+    let mut state = state.game.write().await;
+    if state.game_state.undo().is_err() {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())?);
+    }
+    board_response(&state.game_state)
+}
+
+/// Replays the first `n` moves of the main board's history onto a fresh board
+async fn rewind(State(state): State<RouterState>, Path(n): Path<usize>) -> Result<Response> {
+    let mut state = state.game.write().await;
+    if state.game_state.rewind(n).is_err() {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())?);
+    }
+    board_response(&state.game_state)
+}
+
+/// Moves applied to the current board oldest first, with the row each piece
+/// landed in, for replaying games and reproducing bugs
+async fn moves(State(state): State<RouterState>) -> Result<Response> {
+    let state = state.game.read().await;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(state.game_state.moves())?.into())?)
+}
+
+async fn create_game(State(state): State<RouterState>) -> Result<Response> {
+    let mut sessions = state.sessions.lock().await;
+    let Some(game_id) = sessions.create(GameState::new(state.board_size)) else {
+        return Ok(Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .body(Body::empty())?);
+    };
+    // creating may have evicted an idle game, whose spectate tokens go with it
+    state
+        .spectators
+        .lock()
+        .await
+        .retain(|_, watched| sessions.get(watched).is_some());
+
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "id": game_id }).to_string().into())?)
+}
+
+async fn game_board(
+    State(state): State<RouterState>,
+    Path(game_id): Path<String>,
+) -> Result<Response> {
+    let mut sessions = state.sessions.lock().await;
+    let Some(game) = sessions.get_mut(&game_id) else {
+        return not_found();
+    };
+    board_response(&game.game_state)
+}
+
+async fn game_reset(
+    State(state): State<RouterState>,
+    Path(game_id): Path<String>,
+) -> Result<Response> {
+    let mut sessions = state.sessions.lock().await;
+    let Some(game) = sessions.get_mut(&game_id) else {
+        return not_found();
+    };
+    game.reset();
+    state
+        .spectators
+        .lock()
+        .await
+        .retain(|_, watched| *watched != game_id);
+    board_response(&game.game_state)
+}
+
+async fn game_place(
+    State(state): State<RouterState>,
+    Path((game_id, player, column)): Path<(String, Player, usize)>,
+    AcceptsJson(accepts_json): AcceptsJson,
+) -> Result<Response> {
+    let mut sessions = state.sessions.lock().await;
+    let Some(game) = sessions.get_mut(&game_id) else {
+        return not_found();
+    };
+    game.place(player, column, &state.history, accepts_json)
+        .await
+}
+
+/// Read-only token for watching a game, one per game until it is reset
+async fn game_share(
+    State(state): State<RouterState>,
+    Path(game_id): Path<String>,
+) -> Result<Response> {
+    let mut sessions = state.sessions.lock().await;
+    if sessions.get_mut(&game_id).is_none() {
+        return not_found();
+    }
+
+    let mut spectators = state.spectators.lock().await;
+    let existing = spectators
+        .iter()
+        .find(|(_, watched)| **watched == game_id)
+        .map(|(token, _)| token.clone());
+    let token = existing.unwrap_or_else(|| {
+        let token = random_id();
+        spectators.insert(token.clone(), game_id);
+        token
+    });
+    drop(spectators);
+    drop(sessions);
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "token": token }).to_string().into())?)
+}
+
+/// Board of a shared game, the only thing a spectate token grants
+async fn spectate(State(state): State<RouterState>, Path(token): Path<String>) -> Result<Response> {
+    let Some(game_id) = state.spectators.lock().await.get(&token).cloned() else {
+        return not_found();
+    };
+    let sessions = state.sessions.lock().await;
+    let Some(game) = sessions.get(&game_id) else {
+        return not_found();
+    };
+    board_response(&game.game_state)
 }
 
 struct GameState {
@@ -79,14 +533,795 @@ struct GameState {
     rng: StdRng,
 }
 
+impl GameState {
+    fn new((rows, cols): (usize, usize)) -> Self {
+        Self {
+            game_state: Connect4::with_size(rows, cols),
+            rng: StdRng::seed_from_u64(2024),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.game_state.reset();
+        self.rng = StdRng::seed_from_u64(2024);
+    }
+
+    /// Drops a piece into the 1-based `column`, recording the game once it ends
+    async fn place(
+        &mut self,
+        player: Player,
+        column: usize,
+        history: &GameHistory,
+        accepts_json: bool,
+    ) -> Result<Response> {
+        if !(1..=self.game_state.cols()).contains(&column) {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::empty())?);
+        }
+
+        let legality = Legality::of(&self.game_state, column - 1);
+        if legality != Legality::Ok {
+            if !accepts_json {
+                return Ok(Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .body(self.game_state.to_string().into())?);
+            }
+            let error = match (legality, self.game_state.winner()) {
+                (Legality::GameOver, Some(winner)) => {
+                    serde_json::json!({ "error": "game_over", "winner": winner })
+                }
+                (Legality::GameOver, None) => serde_json::json!({ "error": "board_full" }),
+                _ => serde_json::json!({ "error": "column_full" }),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(CONTENT_TYPE, "application/json")
+                .body(error.to_string().into())?);
+        }
+
+        self.game_state.play(player, column - 1)?;
+        history.record_if_finished(&self.game_state).await?;
+
+        // the piece just played is the topmost one of its column
+        let row = BoardView::from(&self.game_state)
+            .cells
+            .iter()
+            .position(|cells| cells[column - 1].is_some())
+            .ok_or_else(|| anyhow::anyhow!("column {column} is empty after playing it"))?;
+        let mut response = board_response(&self.game_state)?;
+        let headers = response.headers_mut();
+        headers.insert(LANDED_ROW_HEADER, row.into());
+        headers.insert(LANDED_COL_HEADER, (column - 1).into());
+        Ok(response)
+    }
+}
+
+/// 0-based row from the top the placed piece landed in
+const LANDED_ROW_HEADER: &str = "x-landed-row";
+/// 0-based column of the placed piece
+const LANDED_COL_HEADER: &str = "x-landed-col";
+
+pub fn max_games() -> usize {
+    std::env::var(MAX_GAMES_ENV)
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_MAX_GAMES)
+}
+
+/// Rows and columns of new boards, falling back to `BOARD_SIZE` square when
+/// either is unset or invalid or the board would exceed `MAX_CELLS`
+pub fn board_size() -> (usize, usize) {
+    let read = |env| {
+        std::env::var(env)
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .filter(|&size: &usize| size > 0)
+    };
+    match (read(BOARD_ROWS_ENV), read(BOARD_COLS_ENV)) {
+        (Some(rows), Some(cols)) if rows * cols <= MAX_CELLS => (rows, cols),
+        (Some(rows), None) if rows * BOARD_SIZE <= MAX_CELLS => (rows, BOARD_SIZE),
+        (None, Some(cols)) if BOARD_SIZE * cols <= MAX_CELLS => (BOARD_SIZE, cols),
+        _ => (BOARD_SIZE, BOARD_SIZE),
+    }
+}
+
 #[derive(Clone)]
-struct RouterState(Arc<RwLock<GameState>>);
+struct RouterState {
+    game: Arc<RwLock<GameState>>,
+    sessions: Arc<Mutex<SessionStore<GameState>>>,
+    /// Spectate tokens and the game id each one watches
+    spectators: Arc<Mutex<HashMap<String, String>>>,
+    history: Arc<GameHistory>,
+    /// Rows and columns of the main board and every new game
+    board_size: (usize, usize),
+}
 
 impl RouterState {
-    fn new() -> Self {
-        Self(Arc::new(RwLock::new(GameState {
-            game_state: Connect4::new(),
+    fn new(persist: PersistInstance, board_size: (usize, usize)) -> Self {
+        Self {
+            history: Arc::new(GameHistory::new(persist)),
+            game: Arc::new(RwLock::new(GameState::new(board_size))),
+            sessions: Arc::new(Mutex::new(SessionStore::new(
+                max_games(),
+                GAME_IDLE_TIMEOUT,
+            ))),
+            spectators: Arc::default(),
+            board_size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::http::header::ACCEPT;
+    use axum::http::Request;
+    use tower::{Service, ServiceExt};
+
+    async fn send(
+        service: &mut RouterIntoService<Body>,
+        request: Request<Body>,
+    ) -> (StatusCode, String) {
+        let response = service.ready().await.unwrap().call(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn post(uri: &str) -> Request<Body> {
+        Request::post(uri).body(Body::empty()).unwrap()
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    fn test_persist(name: &str) -> PersistInstance {
+        let dir = std::env::temp_dir().join(format!("cch24-day-twelve-{name}-{}", random_id()));
+        PersistInstance::new(dir).unwrap()
+    }
+
+    fn test_routes(name: &str) -> RouterIntoService<Body> {
+        routes(test_persist(name))
+    }
+
+    #[tokio::test]
+    async fn test_game_sessions_are_independent() {
+        let mut service = test_routes("sessions");
+        let (status, body) = send(&mut service, post("/games")).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let game: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let game_id = game["id"].as_str().unwrap();
+
+        let (status, body) = send(&mut service, post(&format!("/{game_id}/place/milk/1"))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.lines().nth(3), Some("⬜🥛⬛⬛⬛⬜"));
+
+        let (_, body) = send(&mut service, get("/board")).await;
+        assert_eq!(body, Connect4::default().to_string());
+
+        let (status, _) = send(&mut service, post("/unknown/place/milk/1")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_finished_game_in_history() {
+        let mut service = test_routes("history");
+        for _ in 0..4 {
+            send(&mut service, post("/place/milk/2")).await;
+        }
+
+        let (status, body) = send(&mut service, get("/history")).await;
+        assert_eq!(status, StatusCode::OK);
+        let history: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let games = history.as_array().unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0]["winner"], "milk");
+        assert_eq!(games[0]["move_count"], 4);
+        assert!(games[0]["ended_at"].is_string());
+
+        // the game is over, so further placements are rejected and not recorded
+        send(&mut service, post("/place/cookie/1")).await;
+        let (_, body) = send(&mut service, get("/history")).await;
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            history
+        );
+    }
+
+    #[tokio::test]
+    async fn test_analyze_best_and_worst() {
+        let mut service = test_routes("analyze");
+        for column in 1..=3 {
+            send(&mut service, post(&format!("/place/milk/{column}"))).await;
+        }
+
+        let (status, body) = send(&mut service, get("/analyze/cookie")).await;
+        assert_eq!(status, StatusCode::OK);
+        let analysis: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(analysis["best"], 4);
+        // every other column loses immediately, the lowest one wins the tie
+        assert_eq!(analysis["worst"], 1);
+        assert_eq!(analysis["scores"].as_array().unwrap().len(), BOARD_SIZE);
+    }
+
+    #[test]
+    fn test_analysis_without_legal_moves() {
+        let analysis = Analysis::new(vec![None; BOARD_SIZE]);
+        assert_eq!(analysis.best, None);
+        assert_eq!(analysis.worst, None);
+    }
+
+    #[tokio::test]
+    async fn test_board_json_column_flags() {
+        let mut service = test_routes("board-json");
+        for _ in 0..BOARD_SIZE {
+            send(&mut service, post("/place/cookie/1")).await;
+        }
+
+        let (status, body) = send(&mut service, get("/board.json")).await;
+        assert_eq!(status, StatusCode::OK);
+        let board: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            board["columns"],
+            serde_json::json!([true, false, false, false])
+        );
+        assert_eq!(
+            board["cells"][3],
+            serde_json::json!(["cookie", null, null, null])
+        );
+
+        send(&mut service, post("/reset")).await;
+        let (_, body) = send(&mut service, get("/board.json")).await;
+        let board: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(board["columns"], serde_json::json!(vec![false; BOARD_SIZE]));
+    }
+
+    fn post_json(uri: &str) -> Request<Body> {
+        Request::post(uri)
+            .header(ACCEPT, "application/json")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_place_unavailable_json() {
+        let mut service = test_routes("unavailable-json");
+        for _ in 0..BOARD_SIZE {
+            send(&mut service, post("/place/milk/1")).await;
+        }
+        let (status, body) = send(&mut service, post_json("/place/cookie/2")).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body, r#"{"error":"game_over","winner":"milk"}"#);
+
+        // the text board stays the default
+        let (status, body) = send(&mut service, post("/place/cookie/2")).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body.ends_with("🥛 wins!\n"));
+
+        send(&mut service, post("/reset")).await;
+        for _ in 0..BOARD_SIZE / 2 {
+            send(&mut service, post("/place/milk/1")).await;
+            send(&mut service, post("/place/cookie/1")).await;
+        }
+        let (status, body) = send(&mut service, post_json("/place/milk/1")).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body, r#"{"error":"column_full"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_place_board_full_json() {
+        let mut service = test_routes("board-full-json");
+        // columns alternate in pairs so no line of four forms
+        let order = [Player::Milk, Player::Cookie];
+        for column in 1..=BOARD_SIZE {
+            for row in 0..BOARD_SIZE {
+                let player = order[(row + column / 2) % 2];
+                let uri = format!("/place/{}/{column}", player.name());
+                let (status, _) = send(&mut service, post(&uri)).await;
+                assert_eq!(status, StatusCode::OK, "{uri}");
+            }
+        }
+        let (status, body) = send(&mut service, post_json("/place/milk/1")).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body, r#"{"error":"board_full"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_reset_reports_was_reset() {
+        let mut service = test_routes("was-reset");
+        send(&mut service, post("/place/milk/1")).await;
+
+        let (status, body) = send(&mut service, post_json("/reset")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"was_reset":true}"#);
+
+        let (status, body) = send(&mut service, post_json("/reset")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"was_reset":false}"#);
+
+        let (_, body) = send(&mut service, post("/reset")).await;
+        assert_eq!(body, Connect4::default().to_string());
+    }
+
+    #[tokio::test]
+    async fn test_place_landed_headers() {
+        let mut service = test_routes("landed");
+        for expected_row in [BOARD_SIZE - 1, BOARD_SIZE - 2] {
+            let response = service
+                .ready()
+                .await
+                .unwrap()
+                .call(post("/place/milk/2"))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers()[LANDED_ROW_HEADER],
+                expected_row.to_string()
+            );
+            assert_eq!(response.headers()[LANDED_COL_HEADER], "1");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_config_defaults() {
+        let (status, body) = send(&mut test_routes("config"), get("/config")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "rows": 4, "cols": 4, "win_length": 4, "theme": "emoji" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ai_is_prompt_on_large_boards() {
+        let state = RouterState::new(test_persist("ai-large"), (6, 7));
+        let mut service = router(state).into_service();
+        for uri in [
+            "/ai/milk",
+            "/analyze/cookie",
+            "/hint/cookie?difficulty=hard",
+        ] {
+            let request = if uri.starts_with("/ai") {
+                post(uri)
+            } else {
+                get(uri)
+            };
+            let started = std::time::Instant::now();
+            let (status, _) = send(&mut service, request).await;
+            assert_eq!(status, StatusCode::OK, "{uri}");
+            assert!(started.elapsed() < Duration::from_secs(5), "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_configured_board_size() {
+        let state = RouterState::new(test_persist("board-size"), (6, 7));
+        let mut service = router(state).into_service();
+        let (_, body) = send(&mut service, get("/config")).await;
+        let config: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            (config["rows"].as_u64(), config["cols"].as_u64()),
+            (Some(6), Some(7))
+        );
+
+        let (status, _) = send(&mut service, post("/place/milk/7")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, board) = send(&mut service, get("/random-board")).await;
+        assert_eq!(board.lines().next().unwrap().chars().count(), 9);
+        assert_eq!(board.lines().nth(6), Some("⬜".repeat(9).as_str()));
+
+        let (_, body) = send(&mut service, post("/games")).await;
+        let game: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let game_id = game["id"].as_str().unwrap();
+        let (status, _) = send(&mut service, post(&format!("/{game_id}/place/milk/7"))).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_board_svg() {
+        let mut service = test_routes("board-svg");
+        send(&mut service, post("/place/milk/1")).await;
+        send(&mut service, post("/place/cookie/2")).await;
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(get("/board.svg"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "image/svg+xml");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&body).matches("<circle").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_board_json_empty_cells() {
+        let mut service = test_routes("board-json-empty");
+        send(&mut service, post("/place/milk/1")).await;
+
+        let (_, body) = send(&mut service, get("/board.json")).await;
+        let board: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            board["cells"][0],
+            serde_json::json!([null, null, null, null])
+        );
+        assert_eq!(
+            board["cells"][3],
+            serde_json::json!(["milk", null, null, null])
+        );
+
+        let (status, body) = send(&mut service, get("/board.json?empty=string")).await;
+        assert_eq!(status, StatusCode::OK);
+        let board: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            board["cells"][0],
+            serde_json::json!(["empty", "empty", "empty", "empty"])
+        );
+        assert_eq!(
+            board["cells"][3],
+            serde_json::json!(["milk", "empty", "empty", "empty"])
+        );
+        assert_eq!(board["winner"], serde_json::Value::Null);
+    }
+
+    async fn can_place(service: &mut RouterIntoService<Body>, column: usize) -> serde_json::Value {
+        let (status, body) = send(service, get(&format!("/can-place/{column}"))).await;
+        assert_eq!(status, StatusCode::OK);
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_can_place_reasons() {
+        let mut service = test_routes("can-place");
+        assert_eq!(
+            can_place(&mut service, 1).await,
+            serde_json::json!({"legal": true, "reason": "ok"})
+        );
+
+        for player in ["milk", "cookie", "milk", "cookie"] {
+            send(&mut service, post(&format!("/place/{player}/1"))).await;
+        }
+        assert_eq!(
+            can_place(&mut service, 1).await,
+            serde_json::json!({"legal": false, "reason": "full"})
+        );
+        assert_eq!(can_place(&mut service, 2).await["reason"], "ok");
+
+        for _ in 0..4 {
+            send(&mut service, post("/place/milk/2")).await;
+        }
+        assert_eq!(
+            can_place(&mut service, 3).await,
+            serde_json::json!({"legal": false, "reason": "game_over"})
+        );
+
+        // checking never changes the board
+        let (_, board) = send(&mut service, get("/board")).await;
+        can_place(&mut service, 3).await;
+        assert_eq!(send(&mut service, get("/board")).await.1, board);
+    }
+
+    #[tokio::test]
+    async fn test_can_place_out_of_bounds() {
+        let mut service = test_routes("can-place-bounds");
+        for column in [0, BOARD_SIZE + 1] {
+            let (status, _) = send(&mut service, get(&format!("/can-place/{column}"))).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_place_uses_board_width() {
+        let history = GameHistory::new(test_persist("board-width"));
+        let mut game = GameState {
+            game_state: Connect4::with_size(6, 7),
             rng: StdRng::seed_from_u64(2024),
-        })))
+        };
+
+        let response = game.place(Player::Milk, 7, &history, false).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = game.place(Player::Milk, 8, &history, false).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_hint_blocks_threat() {
+        let mut service = test_routes("hint");
+        for column in 1..=3 {
+            send(&mut service, post(&format!("/place/milk/{column}"))).await;
+        }
+
+        for uri in ["/hint/cookie", "/hint/cookie?difficulty=hard"] {
+            let (status, body) = send(&mut service, get(uri)).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body, r#"{"column":4}"#);
+        }
+
+        let (status, _) = send(&mut service, get("/hint/cookie?difficulty=brutal")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_hint_keeps_random_board_sequence() {
+        let mut service = test_routes("hint-rng");
+        let (_, expected) = send(&mut service, get("/random-board")).await;
+
+        let mut service = test_routes("hint-rng");
+        send(&mut service, get("/hint/cookie?difficulty=easy")).await;
+        let (_, board) = send(&mut service, get("/random-board")).await;
+        assert_eq!(board, expected);
+    }
+
+    #[tokio::test]
+    async fn test_ai_blocks_threat_and_wins() {
+        let mut service = test_routes("ai");
+        for column in 1..=3 {
+            send(&mut service, post(&format!("/place/milk/{column}"))).await;
+        }
+        let (status, board) = send(&mut service, post("/ai/cookie")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(board.lines().nth(3), Some("⬜🥛🥛🥛🍪⬜"));
+
+        let mut service = test_routes("ai-wins");
+        for column in [1, 1, 1] {
+            send(&mut service, post(&format!("/place/milk/{column}"))).await;
+        }
+        let (status, board) = send(&mut service, post("/ai/milk")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(board.contains("🥛 wins!"), "{board}");
+
+        let (status, _) = send(&mut service, post("/ai/cookie")).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_spectate_is_read_only() {
+        let mut service = test_routes("spectate");
+        let (_, body) = send(&mut service, post("/games")).await;
+        let game: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let game_id = game["id"].as_str().unwrap();
+        send(&mut service, post(&format!("/{game_id}/place/milk/1"))).await;
+
+        let (status, body) = send(&mut service, post(&format!("/{game_id}/share"))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let share: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let token = share["token"].as_str().unwrap();
+
+        let (status, board) = send(&mut service, get(&format!("/spectate/{token}"))).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, player_board) = send(&mut service, get(&format!("/{game_id}/board"))).await;
+        assert_eq!(board, player_board);
+
+        // the token doesn't work as a game id, nor under any mutating route
+        for uri in [
+            format!("/{token}/place/milk/1"),
+            format!("/{token}/reset"),
+            format!("/spectate/{token}/place/milk/1"),
+        ] {
+            let (status, _) = send(&mut service, post(&uri)).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+        }
+        let (status, _) = send(&mut service, post(&format!("/spectate/{token}"))).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(
+            send(&mut service, get(&format!("/spectate/{token}")))
+                .await
+                .1,
+            board
+        );
+
+        let (status, _) = send(&mut service, post("/unknown/share")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_spectate_tokens_are_pruned() {
+        let mut service = test_routes("spectate-prune");
+        let (_, body) = send(&mut service, post("/games")).await;
+        let game: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let game_id = game["id"].as_str().unwrap();
+
+        let share = |body: String| {
+            let share: serde_json::Value = serde_json::from_str(&body).unwrap();
+            share["token"].as_str().unwrap().to_string()
+        };
+        let token = share(
+            send(&mut service, post(&format!("/{game_id}/share")))
+                .await
+                .1,
+        );
+        let again = share(
+            send(&mut service, post(&format!("/{game_id}/share")))
+                .await
+                .1,
+        );
+        assert_eq!(token, again);
+
+        send(&mut service, post(&format!("/{game_id}/reset"))).await;
+        let (status, _) = send(&mut service, get(&format!("/spectate/{token}"))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let fresh = share(
+            send(&mut service, post(&format!("/{game_id}/share")))
+                .await
+                .1,
+        );
+        assert_ne!(token, fresh);
+    }
+
+    #[tokio::test]
+    async fn test_evicted_games_drop_spectate_tokens() {
+        let state = RouterState::new(test_persist("spectate-evict"), (BOARD_SIZE, BOARD_SIZE));
+        *state.sessions.lock().await = SessionStore::new(1, Duration::ZERO);
+        let mut service = router(state.clone()).into_service();
+
+        let (_, body) = send(&mut service, post("/games")).await;
+        let game: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let game_id = game["id"].as_str().unwrap();
+        send(&mut service, post(&format!("/{game_id}/share"))).await;
+        assert_eq!(state.spectators.lock().await.len(), 1);
+
+        let (status, _) = send(&mut service, post("/games")).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(state.spectators.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_undo() {
+        let mut service = test_routes("undo");
+        let (status, _) = send(&mut service, post("/undo")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        send(&mut service, post("/place/milk/2")).await;
+        let (status, body) = send(&mut service, post("/undo")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, Connect4::default().to_string());
+
+        send(&mut service, post("/place/cookie/1")).await;
+        send(&mut service, post("/reset")).await;
+        let (status, _) = send(&mut service, post("/undo")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_moves() {
+        let mut service = test_routes("moves");
+        for (player, column) in [("milk", 1), ("cookie", 3), ("milk", 1)] {
+            send(&mut service, post(&format!("/place/{player}/{column}"))).await;
+        }
+        let (status, _) = send(&mut service, post("/undo")).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = send(&mut service, get("/moves")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!([
+                {"player": "milk", "column": 0, "row": 3},
+                {"player": "cookie", "column": 2, "row": 3},
+            ])
+        );
+
+        send(&mut service, post("/reset")).await;
+        let (_, body) = send(&mut service, get("/moves")).await;
+        assert_eq!(body, "[]");
+    }
+
+    #[tokio::test]
+    async fn test_moves_order() {
+        let mut service = test_routes("moves-order");
+        let sequence = [("milk", 2), ("cookie", 2), ("milk", 4), ("cookie", 2)];
+        for (player, column) in sequence {
+            send(&mut service, post(&format!("/place/{player}/{column}"))).await;
+        }
+
+        let (_, body) = send(&mut service, get("/moves")).await;
+        let moves = serde_json::from_str::<Vec<serde_json::Value>>(&body).unwrap();
+        let played = moves
+            .iter()
+            .map(|played| {
+                (
+                    played["player"].as_str().unwrap(),
+                    played["column"].as_u64().unwrap() as usize + 1,
+                    played["row"].as_u64().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            played,
+            [
+                ("milk", 2, 3),
+                ("cookie", 2, 2),
+                ("milk", 4, 3),
+                ("cookie", 2, 1)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fill() {
+        let mut service = test_routes("fill");
+        send(&mut service, post("/place/cookie/1")).await;
+        let (status, body) = send(&mut service, post("/fill/milk/1/10")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"game_over":false,"placed":3}"#);
+
+        let (_, body) = send(&mut service, post("/fill/cookie/2/2")).await;
+        assert_eq!(body, r#"{"game_over":false,"placed":2}"#);
+        let (_, body) = send(&mut service, post("/fill/cookie/2/5")).await;
+        assert_eq!(body, r#"{"game_over":true,"placed":2}"#);
+        let (_, body) = send(&mut service, post("/fill/milk/3/1")).await;
+        assert_eq!(body, r#"{"game_over":true,"placed":0}"#);
+
+        for uri in ["/fill/milk/0/1", "/fill/milk/5/1"] {
+            let (status, _) = send(&mut service, post(uri)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rewind() {
+        let mut service = test_routes("rewind");
+        for (player, column) in [
+            ("milk", 1),
+            ("cookie", 2),
+            ("milk", 1),
+            ("cookie", 3),
+            ("milk", 4),
+        ] {
+            send(&mut service, post(&format!("/place/{player}/{column}"))).await;
+        }
+
+        let (status, _) = send(&mut service, post("/rewind/6")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = send(&mut service, post("/rewind/2")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, board) = send(&mut service, get("/board")).await;
+        assert_eq!(body, board);
+        let (_, moves) = send(&mut service, get("/moves")).await;
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&moves).unwrap(),
+            serde_json::json!([
+                {"player": "milk", "column": 0, "row": 3},
+                {"player": "cookie", "column": 1, "row": 3},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_board_flip() {
+        let mut service = test_routes("board-flip");
+        send(&mut service, post("/place/cookie/1")).await;
+        send(&mut service, post("/place/milk/1")).await;
+
+        let (_, normal) = send(&mut service, get("/board")).await;
+        let (status, vertical) = send(&mut service, get("/board?flip=vertical")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, horizontal) = send(&mut service, get("/board?flip=horizontal")).await;
+
+        let rows = |board: &str| board.lines().map(str::to_string).collect::<Vec<_>>();
+        let mut reversed = rows(&normal)[..BOARD_SIZE].to_vec();
+        reversed.reverse();
+        assert_eq!(rows(&vertical)[..BOARD_SIZE], reversed);
+        let mirrored = rows(&normal)
+            .iter()
+            .map(|row| row.chars().rev().collect::<String>())
+            .collect::<Vec<_>>();
+        assert_eq!(rows(&horizontal), mirrored);
+        assert_ne!(normal, vertical);
+        assert_ne!(normal, horizontal);
+
+        let (status, _) = send(&mut service, get("/board?flip=diagonal")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
     }
 }
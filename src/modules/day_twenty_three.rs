@@ -1,24 +1,57 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::{self, Display};
 use std::str::FromStr;
 
 use crate::utils::error_handling::{Error, Result};
 use anyhow::anyhow;
-use axum::body::Body;
-use axum::extract::{Multipart, Path};
+use axum::body::{Body, Bytes};
+use axum::extract::{Multipart, Path, Query, State};
+use axum::http::header::CONTENT_TYPE;
 use axum::http::StatusCode;
 use axum::response::Response;
 use axum::routing::{get, post, RouterIntoService};
-use axum::Router;
+use axum::{Json, Router};
+use base64::alphabet::URL_SAFE;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
 use html_escape::encode_quoted_attribute;
 use indoc::formatdoc;
 use serde::{de, ser, Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+/// Environment variable that, set to `false`, makes invalid colors and
+/// ornament states a 400 rather than a 418
+pub const TEAPOT_MODE_ENV: &str = "TEAPOT_MODE";
+
+pub fn teapot_mode() -> bool {
+    !matches!(
+        std::env::var(TEAPOT_MODE_ENV)
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str(),
+        "off" | "false" | "0"
+    )
+}
 
 pub fn routes() -> RouterIntoService<Body> {
+    router(TeapotMode(teapot_mode()))
+}
+
+fn router(teapot_mode: TeapotMode) -> RouterIntoService<Body> {
     Router::new()
         .route("/star", get(star))
         .route("/present/:color", get(present))
+        .route("/present/:color/chain", get(present_chain))
         .route("/ornament/:state/:id", get(ornament))
+        .route("/ornaments", post(ornaments))
+        .route("/ornaments/toggle", post(toggle_ornaments))
         .route("/lockfile", post(lockfile))
+        .route("/lockfile/raw", post(lockfile_raw))
+        .route("/lockfile/diff", post(lockfile_diff))
+        .route("/lockfile/validate", post(lockfile_validate))
+        .route("/lockfile/digest", post(lockfile_digest))
+        .with_state(teapot_mode)
         .into_service()
 }
 
@@ -70,47 +103,155 @@ impl Color {
     }
 }
 
-async fn present(Path(color): Path<String>) -> Result<Response> {
-    let Ok(color) = Color::try_from(color) else {
-        return Ok(Response::builder()
-            .status(StatusCode::IM_A_TEAPOT)
-            .body(Body::empty())?);
+#[derive(Debug, Deserialize)]
+struct PresentQuery {
+    /// Color used in place of an invalid one
+    fallback: Option<String>,
+}
+
+async fn present(
+    State(teapot): State<TeapotMode>,
+    Path(color): Path<String>,
+    Query(query): Query<PresentQuery>,
+) -> Result<Response> {
+    // an invalid fallback is rejected even when the color itself is fine
+    let fallback = match query.fallback.map(Color::try_from).transpose() {
+        Ok(fallback) => fallback,
+        Err(_) => return teapot.reject(),
     };
+    let Some(color) = Color::try_from(color).ok().or(fallback) else {
+        return teapot.reject();
+    };
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(render_present(color).into())?)
+}
+
+/// Every present of one full color cycle starting at `color`, in swap order
+async fn present_chain(
+    State(teapot): State<TeapotMode>,
+    Path(color): Path<String>,
+) -> Result<Response> {
+    let Ok(start) = Color::try_from(color) else {
+        return teapot.reject();
+    };
+    let mut chain = render_present(start);
+    let mut color = start.next();
+    while color != start {
+        chain.push_str(&render_present(color));
+        color = color.next();
+    }
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(chain.into())?)
+}
+
+fn render_present(color: Color) -> String {
     let next_color = color.next();
-    let present = formatdoc! {r#"
+    formatdoc! {r#"
       <div class="present {color}" hx-get="/23/present/{next_color}" hx-swap="outerHTML">
         <div class="ribbon"></div>
         <div class="ribbon"></div>
         <div class="ribbon"></div>
         <div class="ribbon"></div>
       </div>
-    "#};
+    "#}
+}
+
+/// Whether invalid colors and ornament states are answered with a 418
+#[derive(Debug, Clone, Copy)]
+struct TeapotMode(bool);
+
+impl TeapotMode {
+    fn reject(self) -> Result<Response> {
+        let status = if self.0 {
+            StatusCode::IM_A_TEAPOT
+        } else {
+            StatusCode::BAD_REQUEST
+        };
+        Ok(Response::builder().status(status).body(Body::empty())?)
+    }
+}
+
+async fn ornament(
+    State(teapot): State<TeapotMode>,
+    Path((state_str, id)): Path<(String, String)>,
+) -> Result<Response> {
+    let (Some(state), true) = (parse_ornament_state(&state_str), valid_ornament_id(&id)) else {
+        return teapot.reject();
+    };
+
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .body(present.into())?)
+        .body(render_ornament(state, &id).into())?)
+}
+
+#[derive(Debug, Deserialize)]
+struct OrnamentRequest {
+    state: String,
+    id: String,
 }
 
-async fn ornament(Path((state_str, id)): Path<(String, String)>) -> Result<Response> {
-    let state = match state_str.to_lowercase().as_str() {
-        "on" => true,
-        "off" => false,
-        _ => {
+async fn ornaments(Json(requests): Json<Vec<OrnamentRequest>>) -> Result<Response> {
+    render_ornaments(&requests, |state| state)
+}
+
+/// Renders every ornament in the state it would be clicked into, i.e. `on`
+/// ornaments come back `off` and `off` ones come back `on`
+async fn toggle_ornaments(Json(requests): Json<Vec<OrnamentRequest>>) -> Result<Response> {
+    render_ornaments(&requests, next_state)
+}
+
+/// Renders each requested ornament in the state `state_of` maps it to,
+/// rejecting the whole batch on the first invalid entry
+fn render_ornaments(
+    requests: &[OrnamentRequest],
+    state_of: impl Fn(bool) -> bool,
+) -> Result<Response> {
+    let mut rendered = String::new();
+    for (index, request) in requests.iter().enumerate() {
+        let (Some(state), true) = (
+            parse_ornament_state(&request.state),
+            valid_ornament_id(&request.id),
+        ) else {
             return Ok(Response::builder()
-                .status(StatusCode::IM_A_TEAPOT)
-                .body(Body::empty())?)
-        }
-    };
+                .status(StatusCode::BAD_REQUEST)
+                .body(format!("Invalid ornament at index {index}").into())?);
+        };
+        rendered.push_str(&render_ornament(state_of(state), &request.id));
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(rendered.into())?)
+}
+
+/// Parses an ornament state, `true` when the ornament is lit
+fn parse_ornament_state(state: &str) -> Option<bool> {
+    match state.to_lowercase().as_str() {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
 
-    let ornament = formatdoc! {r#"
+/// State an ornament switches to when it is next loaded
+fn next_state(state: bool) -> bool {
+    !state
+}
+
+fn valid_ornament_id(id: &str) -> bool {
+    !id.is_empty()
+}
+
+fn render_ornament(state: bool, id: &str) -> String {
+    formatdoc! {r#"
       <div class="{class}" id="{id}" hx-trigger="load delay:2s once" hx-get="{hx_get}" hx-swap="outerHTML"></div>
     "#,
       class = format!("ornament{}", if state { " on" } else { "" }),
-      id = format!("ornament{id}", id = encode_quoted_attribute(&id)),
-      hx_get = format!("/23/ornament/{next_state}/{id}", id = encode_quoted_attribute(&id), next_state = if state { "off" } else { "on" }),
-    };
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .body(ornament.into())?)
+      id = format!("ornament{id}", id = encode_quoted_attribute(id)),
+      hx_get = format!("/23/ornament/{next_state}/{id}", id = encode_quoted_attribute(id), next_state = if next_state(state) { "on" } else { "off" }),
+    }
 }
 
 /// Parsed Cargo.lock file containing dependencies
@@ -144,7 +285,7 @@ impl FromStr for Checksum {
 
     fn from_str(s: &str) -> Result<Self> {
         // Validate input is non-empty, has even length, and minimum length of 8 characters (4 bytes)
-        if s.is_empty() || s.len() % 2 != 0 || s.len() < 8 {
+        if s.is_empty() || !s.len().is_multiple_of(2) || s.len() < 8 {
             return Err(anyhow!("invalid_checksum").into());
         }
 
@@ -163,9 +304,7 @@ impl FromStr for Checksum {
         }
 
         // Zero-pad the remaining bytes
-        for i in bytes..32 {
-            digest[i] = 0;
-        }
+        digest[bytes..].fill(0);
 
         Ok(Checksum::Sha256(digest))
     }
@@ -228,7 +367,309 @@ impl Serialize for Checksum {
     }
 }
 
-async fn lockfile(mut multipart: Multipart) -> Result<Response> {
+/// Parses a lockfile, mapping failures to the status they are reported with
+fn parse_lockfile(lockfile: &str) -> std::result::Result<Lockfile, StatusCode> {
+    toml::from_str::<Lockfile>(lockfile).map_err(|e| error_status(&e))
+}
+
+fn error_status(error: &toml::de::Error) -> StatusCode {
+    match error.message() {
+        "invalid_checksum" => StatusCode::UNPROCESSABLE_ENTITY,
+        _ => StatusCode::BAD_REQUEST,
+    }
+}
+
+/// Header every package section of a lockfile starts with
+const PACKAGE_HEADER: &str = "[[package]]";
+
+/// Tracks whether a line of TOML starts inside a value, i.e. within a
+/// multi-line string or array, where a line that looks like a header isn't one
+#[derive(Debug, Default)]
+struct ValueScanner {
+    /// Delimiter of the multi-line string left open by the lines so far
+    open_string: Option<&'static str>,
+    /// Brackets opened outside strings and comments and not yet closed
+    depth: usize,
+}
+
+impl ValueScanner {
+    fn in_value(&self) -> bool {
+        self.open_string.is_some() || self.depth > 0
+    }
+
+    fn scan(&mut self, line: &str) {
+        let mut rest = line;
+        loop {
+            if let Some(delimiter) = self.open_string {
+                let Some(end) = closing_quote(rest, delimiter) else {
+                    return;
+                };
+                rest = &rest[end + delimiter.len()..];
+                self.open_string = None;
+            }
+            let Some(start) = rest.find(['#', '"', '\'', '[', ']']) else {
+                return;
+            };
+            rest = &rest[start..];
+            match rest.as_bytes()[0] {
+                b'#' => return,
+                b'[' => self.depth += 1,
+                b']' => self.depth = self.depth.saturating_sub(1),
+                _ => {
+                    if let Some(delimiter) = ["\"\"\"", "'''"]
+                        .into_iter()
+                        .find(|delimiter| rest.starts_with(delimiter))
+                    {
+                        self.open_string = Some(delimiter);
+                        rest = &rest[delimiter.len()..];
+                        continue;
+                    }
+                    let quote = &rest[..1];
+                    // an unterminated string is invalid TOML the parser reports
+                    let Some(end) = closing_quote(&rest[1..], quote) else {
+                        return;
+                    };
+                    rest = &rest[end + 1..];
+                }
+            }
+            rest = &rest[1..];
+        }
+    }
+}
+
+/// Offset of the `delimiter` closing a string, skipping escapes in basic strings
+fn closing_quote(s: &str, delimiter: &str) -> Option<usize> {
+    let escapes = delimiter.starts_with('"');
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if escapes && bytes[i] == b'\\' {
+            i += 2;
+        } else if bytes[i..].starts_with(delimiter.as_bytes()) {
+            return Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Name of the `[table]` or `[[array]]` header a line holds, without whitespace,
+/// and whether it is an array
+fn table_header(line: &str) -> Option<(String, bool)> {
+    let line = line.trim_start();
+    let (rest, is_array) = match line.strip_prefix("[[") {
+        Some(rest) => (rest, true),
+        None => (line.strip_prefix('[')?, false),
+    };
+    let end = rest.find(']')?;
+    let name = rest[..end].chars().filter(|c| !c.is_whitespace()).collect();
+    Some((name, is_array))
+}
+
+/// Reads a lockfile a line at a time and parses each `[[package]]` section on
+/// its own, so only the section being read is buffered instead of the whole
+/// file and its TOML tree.
+///
+/// Failures are reported as `parse_lockfile` would for the whole file: invalid
+/// UTF-8 first, then invalid TOML anywhere as a 400, then the first package
+/// that doesn't deserialize. Besides each section parsing, the file is only
+/// valid TOML if no `[table]` header repeats across sections and no key path
+/// outside `package` is defined again by a later section, unless both define
+/// it as a table. Both are tracked by name; inline tables count as tables.
+#[derive(Debug, Default)]
+struct PackageStream {
+    /// Bytes of the current line, not yet terminated
+    partial: Vec<u8>,
+    /// Offset of `partial` from the start of the lockfile
+    offset: usize,
+    /// Lines of the section being read
+    section: String,
+    scanner: ValueScanner,
+    /// `[table]` headers seen so far, outside of arrays of tables
+    tables: HashSet<String>,
+    /// `[[array]]` headers seen so far, whose sub-tables may repeat per element
+    arrays: HashSet<String>,
+    /// Key paths outside `package` defined by earlier sections, and whether
+    /// each holds a table
+    defined: HashMap<String, Defined>,
+    packages: Vec<Package>,
+    saw_package: bool,
+    /// Set once a section is not valid TOML, after which sections are only
+    /// checked for UTF-8
+    invalid_toml: bool,
+    first_error: Option<StatusCode>,
+}
+
+impl PackageStream {
+    /// Feeds the next chunk of the lockfile, failing with the position of the
+    /// first byte that is not valid UTF-8
+    fn push(&mut self, mut bytes: &[u8]) -> std::result::Result<(), usize> {
+        while let Some(end) = bytes.iter().position(|&b| b == b'\n') {
+            self.partial.extend_from_slice(&bytes[..=end]);
+            bytes = &bytes[end + 1..];
+            let line = std::mem::take(&mut self.partial);
+            self.push_line(line)?;
+        }
+        self.partial.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn push_line(&mut self, line: Vec<u8>) -> std::result::Result<(), usize> {
+        let len = line.len();
+        let line =
+            String::from_utf8(line).map_err(|e| self.offset + e.utf8_error().valid_up_to())?;
+        self.offset += len;
+        // a header line only opens a section when it isn't inside a multi-line
+        // string or array, so splitting there never changes how the file parses
+        let at_header = !self.scanner.in_value();
+        self.scanner.scan(&line);
+        if at_header {
+            if let Some((name, is_array)) = table_header(&line) {
+                self.note_header(name, is_array);
+            }
+            if line.trim() == PACKAGE_HEADER {
+                self.flush_section();
+            }
+        }
+        self.section.push_str(&line);
+        Ok(())
+    }
+
+    /// Flags a `[table]` defined twice, which is only caught when both
+    /// definitions land in the same section otherwise
+    fn note_header(&mut self, name: String, is_array: bool) {
+        if is_array {
+            self.arrays.insert(name);
+            return;
+        }
+        let in_array = self.arrays.iter().any(|array| {
+            name.strip_prefix(array.as_str())
+                .is_some_and(|rest| rest.starts_with('.'))
+        });
+        if !in_array && !self.tables.insert(name) {
+            self.invalid_toml = true;
+        }
+    }
+
+    fn flush_section(&mut self) {
+        let section = std::mem::take(&mut self.section);
+        if self.invalid_toml {
+            return;
+        }
+        let Ok(table) = toml::from_str::<toml::Table>(&section) else {
+            self.invalid_toml = true;
+            return;
+        };
+        if !self.note_definitions(&table, "") {
+            self.invalid_toml = true;
+            return;
+        }
+        if !table.contains_key("package") {
+            return;
+        }
+        self.saw_package = true;
+        match table.try_into::<Lockfile>() {
+            Ok(lockfile) => self.packages.extend(lockfile.package),
+            Err(e) => {
+                self.first_error.get_or_insert(error_status(&e));
+            }
+        }
+    }
+
+    /// Records the key paths a section defines under `prefix`, failing if one
+    /// was already defined other than as a table by both sections
+    fn note_definitions(&mut self, table: &toml::Table, prefix: &str) -> bool {
+        for (key, value) in table {
+            if prefix.is_empty() && key == "package" {
+                continue;
+            }
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{prefix}.{key}")
+            };
+            if self.arrays.contains(&path) {
+                continue;
+            }
+            let defined = match value {
+                toml::Value::Table(_) => Defined::Table,
+                _ => Defined::Value,
+            };
+            match self.defined.insert(path.clone(), defined) {
+                None => {}
+                Some(Defined::Table) if defined == Defined::Table => {}
+                Some(_) => return false,
+            }
+            if let toml::Value::Table(table) = value {
+                if !self.note_definitions(table, &path) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Packages of the whole lockfile once every chunk has been pushed
+    fn finish(mut self) -> std::result::Result<Vec<Package>, LockfileRejection> {
+        let line = std::mem::take(&mut self.partial);
+        self.push_line(line)
+            .map_err(LockfileRejection::InvalidUtf8)?;
+        self.flush_section();
+
+        if self.invalid_toml || !self.saw_package {
+            return Err(LockfileRejection::Status(StatusCode::BAD_REQUEST));
+        }
+        match self.first_error {
+            Some(status) => Err(LockfileRejection::Status(status)),
+            None => Ok(self.packages),
+        }
+    }
+}
+
+/// What a key path outside `package` was defined as by an earlier section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Defined {
+    Table,
+    Value,
+}
+
+/// Why a streamed lockfile was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockfileRejection {
+    /// Offset of the first byte that is not valid UTF-8
+    InvalidUtf8(usize),
+    Status(StatusCode),
+}
+
+impl LockfileRejection {
+    fn response(self) -> Result<Response> {
+        match self {
+            Self::InvalidUtf8(position) => Ok(invalid_utf8_at(position)),
+            Self::Status(status) => Ok(Response::builder().status(status).body(Body::empty())?),
+        }
+    }
+}
+
+/// 400 pointing at the first byte of an upload that is not valid UTF-8
+fn invalid_utf8(error: std::string::FromUtf8Error) -> Result<Response> {
+    Ok(invalid_utf8_at(error.utf8_error().valid_up_to()))
+}
+
+fn invalid_utf8_at(position: usize) -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header(CONTENT_TYPE, "application/json")
+        .body(
+            serde_json::json!({ "error": "invalid_utf8", "position": position })
+                .to_string()
+                .into(),
+        )
+        .unwrap()
+}
+
+/// Collects the contents of the `lockfile` multipart field
+async fn lockfile_field(multipart: &mut Multipart) -> Result<Vec<u8>> {
     let mut lockfile_bytes = vec![];
 
     while let Ok(Some(field)) = multipart.next_field().await {
@@ -240,40 +681,88 @@ async fn lockfile(mut multipart: Multipart) -> Result<Response> {
         lockfile_bytes.extend(data);
     }
 
-    if lockfile_bytes.is_empty() {
-        return Ok(Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .body(Body::empty())?);
-    }
+    Ok(lockfile_bytes)
+}
 
-    let lockfile_str = String::from_utf8(lockfile_bytes).map_err(|e| anyhow!(e.to_string()))?;
+/// Order the lockfile dots are rendered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DotOrder {
+    /// Package order within the file
+    #[default]
+    File,
+    /// Sorted by checksum, stable across reordered lockfiles
+    Checksum,
+}
+
+/// How the lockfile dots are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DotGrouping {
+    /// One flat list of dots
+    #[default]
+    None,
+    /// Dots under a header per color, colors ordered by their `#rrggbb` value
+    Color,
+}
 
-    let mut lockfile: Option<Lockfile> = None;
-    match toml::from_str::<Lockfile>(&lockfile_str) {
-        Ok(parsed) => {
-            lockfile = Some(parsed);
+#[derive(Debug, Deserialize)]
+struct LockfileQuery {
+    #[serde(default)]
+    sort: DotOrder,
+    #[serde(default)]
+    group: DotGrouping,
+}
+
+/// Streams the `lockfile` field into the parser chunk by chunk
+async fn lockfile(
+    Query(query): Query<LockfileQuery>,
+    mut multipart: Multipart,
+) -> Result<Response> {
+    let mut stream = PackageStream::default();
+    while let Ok(Some(mut field)) = multipart.next_field().await {
+        if field.name() != Some("lockfile") {
+            continue;
         }
-        Err(e) => match e.message() {
-            "invalid_checksum" => {
-                return Ok(Response::builder()
-                    .status(StatusCode::UNPROCESSABLE_ENTITY)
-                    .body(Body::empty())?);
+        while let Some(chunk) = field.chunk().await? {
+            if let Err(position) = stream.push(&chunk) {
+                return Ok(invalid_utf8_at(position));
             }
-            _ => {
-                return Ok(Response::builder()
-                    .status(StatusCode::BAD_REQUEST)
-                    .body(Body::empty())?);
-            }
-        },
+        }
     }
+    render_lockfile(stream, &query)
+}
+
+/// base64url with or without padding
+const BASE64_URL: GeneralPurpose = GeneralPurpose::new(
+    &URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
 
-    let Some(packages) = lockfile.map(|lockfile| lockfile.package) else {
+async fn lockfile_raw(Query(query): Query<LockfileQuery>, body: Bytes) -> Result<Response> {
+    let Ok(lockfile_bytes) = BASE64_URL.decode(body.trim_ascii()) else {
         return Ok(Response::builder()
             .status(StatusCode::BAD_REQUEST)
             .body(Body::empty())?);
     };
+    let mut stream = PackageStream::default();
+    if let Err(position) = stream.push(&lockfile_bytes) {
+        return Ok(invalid_utf8_at(position));
+    }
+    render_lockfile(stream, &query)
+}
+
+/// Renders a dot per package checksum of an uploaded lockfile
+fn render_lockfile(stream: PackageStream, query: &LockfileQuery) -> Result<Response> {
+    let mut packages = match stream.finish() {
+        Ok(packages) => packages,
+        Err(rejection) => return rejection.response(),
+    };
+    if query.sort == DotOrder::Checksum {
+        packages.sort_by(|a, b| a.checksum.cmp(&b.checksum));
+    }
 
-    let dots = packages
+    let mut dots = packages
         .iter()
         .filter_map(|p| {
             let Some(checksum) = &p.checksum else {
@@ -286,13 +775,729 @@ async fn lockfile(mut multipart: Multipart) -> Result<Response> {
                     format!("{:.2}", bytes[4]),
                 ),
             };
-            Some(formatdoc! {r#"
+            let dot = formatdoc! {r#"
               <div style="background-color:{color};top:{top}px;left:{left}px;"></div>
-            "#})
+            "#};
+            Some((color, dot))
         })
         .collect::<Vec<_>>();
 
+    let body = match query.group {
+        DotGrouping::None => dots
+            .into_iter()
+            .map(|(_, dot)| dot)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        DotGrouping::Color => {
+            // stable, so dots of one color keep their relative order
+            dots.sort_by(|(a, _), (b, _)| a.cmp(b));
+            dots.chunk_by(|(a, _), (b, _)| a == b)
+                .map(|group| {
+                    let color = &group[0].0;
+                    let dots = group.iter().map(|(_, dot)| dot.as_str());
+                    let header = format!("<h3 style=\"color:{color};\">{color}</h3>");
+                    std::iter::once(header.as_str())
+                        .chain(dots)
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    };
+
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .body(dots.join("\n").into())?)
+        .body(body.into())?)
+}
+
+/// Summary of a lockfile that parsed with only valid checksums
+#[derive(Debug, Serialize)]
+struct LockfileSummary {
+    packages: usize,
+    checksums: usize,
+}
+
+async fn lockfile_validate(mut multipart: Multipart) -> Result<Response> {
+    let lockfile_bytes = lockfile_field(&mut multipart).await?;
+    if lockfile_bytes.is_empty() {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())?);
+    }
+
+    let parsed = String::from_utf8(lockfile_bytes)
+        .map_err(|e| e.to_string())
+        .and_then(|lockfile| {
+            toml::from_str::<Lockfile>(&lockfile).map_err(|e| e.message().to_string())
+        });
+    let packages = match parsed {
+        Ok(lockfile) => lockfile.package,
+        Err(reason) => {
+            return Ok(Response::builder()
+                .status(StatusCode::UNPROCESSABLE_ENTITY)
+                .header(CONTENT_TYPE, "application/json")
+                .body(serde_json::json!({ "error": reason }).to_string().into())?);
+        }
+    };
+
+    let summary = LockfileSummary {
+        packages: packages.len(),
+        checksums: packages.iter().filter(|p| p.checksum.is_some()).count(),
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&summary)?))?)
+}
+
+/// Hash used for the aggregate digest of a lockfile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DigestAlgo {
+    #[default]
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl DigestAlgo {
+    /// Lowercase hex digest of `data`
+    fn hex_digest(&self, data: &[u8]) -> String {
+        let digest = match self {
+            DigestAlgo::Sha256 => Sha256::digest(data).to_vec(),
+            DigestAlgo::Sha512 => Sha512::digest(data).to_vec(),
+            DigestAlgo::Blake3 => blake3::hash(data).as_bytes().to_vec(),
+        };
+        digest.iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DigestQuery {
+    #[serde(default)]
+    algo: DigestAlgo,
+}
+
+#[derive(Debug, Serialize)]
+struct LockfileDigest {
+    algo: DigestAlgo,
+    digest: String,
+}
+
+/// Single digest over every package checksum, sorted so package order in the
+/// lockfile doesn't change it
+async fn lockfile_digest(
+    Query(query): Query<DigestQuery>,
+    mut multipart: Multipart,
+) -> Result<Response> {
+    let lockfile_bytes = lockfile_field(&mut multipart).await?;
+    if lockfile_bytes.is_empty() {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())?);
+    }
+
+    let lockfile_str = match String::from_utf8(lockfile_bytes) {
+        Ok(lockfile) => lockfile,
+        Err(e) => return invalid_utf8(e),
+    };
+    let packages = match parse_lockfile(&lockfile_str) {
+        Ok(lockfile) => lockfile.package,
+        Err(status) => {
+            return Ok(Response::builder().status(status).body(Body::empty())?);
+        }
+    };
+
+    let checksums = packages
+        .into_iter()
+        .filter_map(|p| p.checksum)
+        .collect::<BTreeSet<_>>();
+    let input = checksums
+        .iter()
+        .map(|checksum| format!("{checksum}\n"))
+        .collect::<String>();
+    let digest = LockfileDigest {
+        algo: query.algo,
+        digest: query.algo.hex_digest(input.as_bytes()),
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&digest)?))?)
+}
+
+/// Checksums found in only one of the two compared lockfiles
+#[derive(Debug, Serialize)]
+struct LockfileDiff {
+    only_in_a: BTreeSet<Checksum>,
+    only_in_b: BTreeSet<Checksum>,
+}
+
+fn checksums(lockfile: Lockfile) -> BTreeSet<Checksum> {
+    lockfile
+        .package
+        .into_iter()
+        .filter_map(|p| p.checksum)
+        .collect()
+}
+
+async fn lockfile_diff(mut multipart: Multipart) -> Result<Response> {
+    let mut a_bytes = vec![];
+    let mut b_bytes = vec![];
+
+    while let Ok(Some(field)) = multipart.next_field().await {
+        let name = field.name().map(|s| s.to_string()).unwrap_or_default();
+        let target = match name.as_str() {
+            "a" => &mut a_bytes,
+            "b" => &mut b_bytes,
+            _ => continue,
+        };
+        let data = field.bytes().await?;
+        target.extend(data);
+    }
+
+    if a_bytes.is_empty() || b_bytes.is_empty() {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())?);
+    }
+
+    let (a_str, b_str) = match (String::from_utf8(a_bytes), String::from_utf8(b_bytes)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => return invalid_utf8(e),
+    };
+
+    let (a, b) = match (parse_lockfile(&a_str), parse_lockfile(&b_str)) {
+        (Ok(a), Ok(b)) => (checksums(a), checksums(b)),
+        (Err(status), _) | (_, Err(status)) => {
+            return Ok(Response::builder().status(status).body(Body::empty())?);
+        }
+    };
+
+    let diff = LockfileDiff {
+        only_in_a: a.difference(&b).cloned().collect(),
+        only_in_b: b.difference(&a).cloned().collect(),
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&diff)?))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::http::{header, Request};
+    use tower::ServiceExt;
+
+    async fn get_present(uri: &str) -> (StatusCode, String) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = routes().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_present_invalid_color() {
+        let (status, _) = get_present("/present/green").await;
+        assert_eq!(status, StatusCode::IM_A_TEAPOT);
+    }
+
+    #[tokio::test]
+    async fn test_teapot_mode() {
+        for (teapot_mode, expected) in [
+            (true, StatusCode::IM_A_TEAPOT),
+            (false, StatusCode::BAD_REQUEST),
+        ] {
+            for uri in ["/present/green", "/present/green/chain", "/ornament/dim/1"] {
+                let request = Request::get(uri).body(Body::empty()).unwrap();
+                let response = router(TeapotMode(teapot_mode))
+                    .oneshot(request)
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), expected, "{uri}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_present_fallback_color() {
+        let (status, body) = get_present("/present/green?fallback=red").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(r#"class="present red""#));
+        assert!(body.contains(r#"hx-get="/23/present/blue""#));
+
+        let (status, _) = get_present("/present/green?fallback=green").await;
+        assert_eq!(status, StatusCode::IM_A_TEAPOT);
+    }
+
+    #[tokio::test]
+    async fn test_present_chain() {
+        let (status, body) = get_present("/present/purple/chain").await;
+        assert_eq!(status, StatusCode::OK);
+        for color in ["red", "blue", "purple"] {
+            let class = format!(r#"class="present {color}""#);
+            assert_eq!(body.matches(&class).count(), 1, "{color}");
+        }
+        assert!(body.starts_with(r#"<div class="present purple" hx-get="/23/present/red""#));
+
+        let (status, _) = get_present("/present/green/chain").await;
+        assert_eq!(status, StatusCode::IM_A_TEAPOT);
+    }
+
+    async fn post_ornaments(body: &str) -> (StatusCode, String) {
+        post_ornaments_to("/ornaments", body).await
+    }
+
+    async fn post_ornaments_to(uri: &str, body: &str) -> (StatusCode, String) {
+        let request = Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = routes().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_ornaments_batch() {
+        let (status, body) =
+            post_ornaments(r#"[{"state":"on","id":"1"},{"state":"OFF","id":"<2>"}]"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            [render_ornament(true, "1"), render_ornament(false, "<2>")].concat()
+        );
+        assert!(body.contains(r#"id="ornament&lt;2&gt;""#));
+    }
+
+    #[tokio::test]
+    async fn test_ornaments_toggle() {
+        let (status, body) = post_ornaments_to(
+            "/ornaments/toggle",
+            r#"[{"state":"on","id":"1"},{"state":"off","id":"2"}]"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            [render_ornament(false, "1"), render_ornament(true, "2")].concat()
+        );
+
+        let (status, body) =
+            post_ornaments_to("/ornaments/toggle", r#"[{"state":"on","id":""}]"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, "Invalid ornament at index 0");
+    }
+
+    #[tokio::test]
+    async fn test_ornaments_batch_invalid_entry() {
+        let (status, body) =
+            post_ornaments(r#"[{"state":"on","id":"1"},{"state":"dim","id":"2"}]"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, "Invalid ornament at index 1");
+    }
+
+    const BOUNDARY: &str = "lockfile-boundary";
+
+    async fn post_multipart(uri: &str, fields: &[(&str, &str)]) -> (StatusCode, String) {
+        let fields = fields
+            .iter()
+            .map(|(name, content)| (*name, content.as_bytes()))
+            .collect::<Vec<_>>();
+        post_multipart_bytes(uri, &fields).await
+    }
+
+    async fn post_multipart_bytes(uri: &str, fields: &[(&str, &[u8])]) -> (StatusCode, String) {
+        let mut body = vec![];
+        for (name, content) in fields {
+            body.extend(
+                format!("--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n")
+                    .as_bytes(),
+            );
+            body.extend(*content);
+            body.extend(b"\r\n");
+        }
+        body.extend(format!("--{BOUNDARY}--\r\n").as_bytes());
+
+        let request = Request::post(uri)
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        let response = routes().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn lockfile_with(checksums: &[&str]) -> String {
+        checksums
+            .iter()
+            .map(|checksum| format!("[[package]]\nchecksum = \"{checksum}\"\n"))
+            .collect()
+    }
+
+    #[test]
+    fn test_digest_algo_vectors() {
+        assert_eq!(
+            DigestAlgo::Sha256.hex_digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            DigestAlgo::Sha512.hex_digest(b"abc"),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+        assert_eq!(
+            DigestAlgo::Blake3.hex_digest(b"abc"),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+    }
+
+    async fn lockfile_digest(uri: &str, lockfile: &str) -> serde_json::Value {
+        let (status, body) = post_multipart(uri, &[("lockfile", lockfile)]).await;
+        assert_eq!(status, StatusCode::OK);
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_digest_algorithms() {
+        let lockfile = lockfile_with(&["aaaaaaaa", "bbbbbbbb"]);
+        let reordered = lockfile_with(&["bbbbbbbb", "aaaaaaaa"]);
+
+        let mut digests = vec![];
+        for (algo, hex_len) in [("sha256", 64), ("sha512", 128), ("blake3", 64)] {
+            let uri = format!("/lockfile/digest?algo={algo}");
+            let digest = lockfile_digest(&uri, &lockfile).await;
+            assert_eq!(digest["algo"], algo);
+            assert_eq!(digest["digest"].as_str().unwrap().len(), hex_len);
+            assert_eq!(digest, lockfile_digest(&uri, &reordered).await);
+            digests.push(digest["digest"].as_str().unwrap().to_string());
+        }
+        assert_eq!(digests.iter().collect::<BTreeSet<_>>().len(), 3);
+
+        let default = lockfile_digest("/lockfile/digest", &lockfile).await;
+        assert_eq!(default["algo"], "sha256");
+        assert_eq!(default["digest"], digests[0]);
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_digest_unknown_algo() {
+        let lockfile = lockfile_with(&["aaaaaaaa"]);
+        let (status, _) =
+            post_multipart("/lockfile/digest?algo=md5", &[("lockfile", &lockfile)]).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_diff() {
+        let a = lockfile_with(&["aaaaaaaa", "cccccccc"]);
+        let b = lockfile_with(&["cccccccc", "bbbbbbbb", "dddddddd"]);
+        let (status, body) = post_multipart("/lockfile/diff", &[("a", &a), ("b", &b)]).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let pad = "0".repeat(56);
+        let diff: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            diff,
+            serde_json::json!({
+                "only_in_a": [format!("aaaaaaaa{pad}")],
+                "only_in_b": [format!("bbbbbbbb{pad}"), format!("dddddddd{pad}")],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_diff_invalid_checksum() {
+        let a = lockfile_with(&["aaaaaaaa"]);
+        let b = lockfile_with(&["not-hex!"]);
+        let (status, _) = post_multipart("/lockfile/diff", &[("a", &a), ("b", &b)]).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_validate() {
+        let lockfile = lockfile_with(&["aaaaaaaa", "bbbbbbbb"]) + "[[package]]\n";
+        let (status, body) = post_multipart("/lockfile/validate", &[("lockfile", &lockfile)]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "packages": 3, "checksums": 2 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_validate_invalid_checksum() {
+        let lockfile = lockfile_with(&["aaaaaaaa", "not-hex!"]);
+        let (status, body) = post_multipart("/lockfile/validate", &[("lockfile", &lockfile)]).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "error": "invalid_checksum" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_invalid_utf8() {
+        let mut lockfile = lockfile_with(&["aaaaaaaa"]).into_bytes();
+        let position = lockfile.len();
+        lockfile.extend([0xff, 0xfe]);
+        let (status, body) = post_multipart_bytes("/lockfile", &[("lockfile", &lockfile)]).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "error": "invalid_utf8", "position": position })
+        );
+    }
+
+    #[test]
+    fn test_package_stream_buffers_one_section() {
+        let hashes = (0..20_000).map(|i| format!("{i:08x}")).collect::<Vec<_>>();
+        let lockfile = "version = 3\n\n".to_string()
+            + &lockfile_with(&hashes.iter().map(String::as_str).collect::<Vec<_>>());
+
+        let mut stream = PackageStream::default();
+        for chunk in lockfile.as_bytes().chunks(4096) {
+            stream.push(chunk).unwrap();
+            assert!(stream.section.len() + stream.partial.len() < 256);
+        }
+        assert_eq!(stream.finish().unwrap().len(), hashes.len());
+    }
+
+    #[test]
+    fn test_package_stream_error_precedence() {
+        let finish = |lockfile: &[u8]| {
+            let mut stream = PackageStream::default();
+            stream
+                .push(lockfile)
+                .map_err(LockfileRejection::InvalidUtf8)?;
+            stream.finish()
+        };
+
+        let invalid_checksum = lockfile_with(&["not-hex!", "aaaaaaaa"]);
+        assert_eq!(
+            finish(invalid_checksum.as_bytes()).unwrap_err(),
+            LockfileRejection::Status(StatusCode::UNPROCESSABLE_ENTITY)
+        );
+
+        // invalid TOML later in the file outranks an earlier bad checksum
+        let invalid_toml = invalid_checksum.clone()
+            + "[[package]]
+checksum = \n";
+        assert_eq!(
+            finish(invalid_toml.as_bytes()).unwrap_err(),
+            LockfileRejection::Status(StatusCode::BAD_REQUEST)
+        );
+
+        // and invalid UTF-8 outranks both
+        let mut invalid_utf8 = invalid_toml.into_bytes();
+        let position = invalid_utf8.len() + 2;
+        invalid_utf8.extend(b"# \xff\n");
+        assert_eq!(
+            finish(&invalid_utf8).unwrap_err(),
+            LockfileRejection::InvalidUtf8(position)
+        );
+
+        assert_eq!(
+            finish(b"version = 3\n").unwrap_err(),
+            LockfileRejection::Status(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            finish(b"").unwrap_err(),
+            LockfileRejection::Status(StatusCode::BAD_REQUEST)
+        );
+    }
+
+    #[test]
+    fn test_package_stream_matches_whole_file_parse() {
+        let package =
+            |checksum: &str| format!("[[package]]\nname = \"a\"\nchecksum = \"{checksum}\"\n");
+        let lockfiles = [
+            // header-looking lines inside multi-line strings and arrays
+            format!(
+                "{}description = \"\"\"\n[[package]]\nchecksum = \\\"\"\"\"\n{}",
+                package("aaaaaaaa"),
+                package("bbbbbbbb")
+            ),
+            format!(
+                "{}notes = '''\n[[package]]\n[metadata]\n'''\n{}",
+                package("aaaaaaaa"),
+                package("bbbbbbbb")
+            ),
+            format!(
+                "{}matrix = [\n  [1, 2],\n  [1, 2],\n]\n{}",
+                package("aaaaaaaa"),
+                package("bbbbbbbb")
+            ),
+            // sub-tables of different packages share a name
+            format!(
+                "{}[package.source]\nkind = \"git\"\n{}[package.source]\nkind = \"git\"\n",
+                package("aaaaaaaa"),
+                package("bbbbbbbb")
+            ),
+            // a table defined again in a later section
+            format!(
+                "[metadata]\nx = 1\n{}[metadata] # again\ny = 2\n",
+                package("aaaaaaaa")
+            ),
+            format!(
+                "{}[ metadata ]\nx = 1\n{}[metadata]\ny = 2\n",
+                package("aaaaaaaa"),
+                package("bbbbbbbb")
+            ),
+            format!(
+                "{}[metadata]\n[metadata.sub]\n{}[metadata.other]\n",
+                package("aaaaaaaa"),
+                package("bbbbbbbb")
+            ),
+            // a key defined again as a table, or a table again as a key
+            format!(
+                "[meta]\nsub = 1\n{}[meta.sub]\nx = 1\n",
+                package("aaaaaaaa")
+            ),
+            format!("[metadata]\na = 1\n{}[metadata.a]\n", package("aaaaaaaa")),
+            format!(
+                "[metadata.a]\nx = 1\n{}[metadata]\na = 1\n",
+                package("aaaaaaaa")
+            ),
+            format!("version = 3\n{}[version]\n", package("aaaaaaaa")),
+            // dotted keys extending a table from another section
+            format!(
+                "[metadata.a]\nx = 1\n{}[metadata]\nb.y = 2\n",
+                package("aaaaaaaa")
+            ),
+        ];
+
+        for lockfile in lockfiles {
+            let mut stream = PackageStream::default();
+            stream.push(lockfile.as_bytes()).unwrap();
+            let streamed = stream.finish().map(|packages| packages.len());
+            let whole = parse_lockfile(&lockfile)
+                .map(|lockfile| lockfile.package.len())
+                .map_err(LockfileRejection::Status);
+            assert_eq!(streamed, whole, "{lockfile}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_large() {
+        let hashes = (0..5_000).map(|i| format!("{i:08x}")).collect::<Vec<_>>();
+        let lockfile = lockfile_with(&hashes.iter().map(String::as_str).collect::<Vec<_>>());
+        let (status, body) = post_multipart("/lockfile", &[("lockfile", &lockfile)]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.matches("<div").count(), hashes.len());
+    }
+
+    async fn post_raw(body: String) -> (StatusCode, String) {
+        let request = Request::post("/lockfile/raw")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(Body::from(body))
+            .unwrap();
+        let response = routes().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_raw() {
+        let lockfile =
+            lockfile_with(&["337789faa0372648a8ac286b2f92a53121fe118f12e29009ac504872a5413cc6"]);
+        let (status, body) = post_raw(BASE64_URL.encode(&lockfile)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body.trim(),
+            r#"<div style="background-color:#337789;top:250px;left:160px;"></div>"#
+        );
+
+        let (_, multipart) = post_multipart("/lockfile", &[("lockfile", &lockfile)]).await;
+        assert_eq!(body, multipart);
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_raw_invalid_base64() {
+        let (status, _) = post_raw("not base64!".to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_sorted_by_checksum() {
+        let a = lockfile_with(&["cccccccccc", "aaaaaaaaaa", "bbbbbbbbbb"]);
+        let b = lockfile_with(&["bbbbbbbbbb", "cccccccccc", "aaaaaaaaaa"]);
+
+        let (status, sorted_a) =
+            post_multipart("/lockfile?sort=checksum", &[("lockfile", &a)]).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, sorted_b) = post_multipart("/lockfile?sort=checksum", &[("lockfile", &b)]).await;
+        assert_eq!(sorted_a, sorted_b);
+        assert!(sorted_a.find("#aaaaaa").unwrap() < sorted_a.find("#bbbbbb").unwrap());
+
+        // file order stays the default
+        let (_, unsorted_a) = post_multipart("/lockfile", &[("lockfile", &a)]).await;
+        let (_, unsorted_b) = post_multipart("/lockfile", &[("lockfile", &b)]).await;
+        assert_ne!(unsorted_a, unsorted_b);
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_grouped_by_color() {
+        let lockfile = lockfile_with(&["bbbbbb0102", "aaaaaa0304", "bbbbbb0506"]);
+        let (status, body) =
+            post_multipart("/lockfile?group=color", &[("lockfile", &lockfile)]).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let lines = body.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                r#"<h3 style="color:#aaaaaa;">#aaaaaa</h3>"#,
+                r#"<div style="background-color:#aaaaaa;top:3px;left:4px;"></div>"#,
+                "",
+                r#"<h3 style="color:#bbbbbb;">#bbbbbb</h3>"#,
+                r#"<div style="background-color:#bbbbbb;top:1px;left:2px;"></div>"#,
+                "",
+                r#"<div style="background-color:#bbbbbb;top:5px;left:6px;"></div>"#,
+            ]
+        );
+
+        let (_, ungrouped) = post_multipart("/lockfile", &[("lockfile", &lockfile)]).await;
+        assert!(!ungrouped.contains("<h3"));
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_package_sections_only() {
+        let snippet = indoc::indoc! {r#"
+            [[package]]
+            name = "addr2line"
+            version = "0.24.2"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "dfbe277e56a376000877090da837660b4427aad530e3028d44e0bffe4f89a1c1"
+
+            [[package]]
+            name = "shuttlings-cch24"
+            version = "0.1.0"
+        "#};
+        let (status, body) = post_multipart("/lockfile", &[("lockfile", snippet)]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            "<div style=\"background-color:#dfbe27;top:126px;left:86px;\"></div>\n"
+        );
+    }
 }
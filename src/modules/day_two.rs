@@ -1,11 +1,15 @@
 use axum::body::Body;
 use axum::extract::Query;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, RouterIntoService};
 use axum::Router;
 use serde::Deserialize;
 
 use crate::utils::error_handling::Result;
-use crate::utils::network_address::{IPv4Addr, IPv6Addr};
+use crate::utils::error_responses::bad_request;
+use crate::utils::network_address::{IPv4Addr, IPv4Cidr, IPv6Addr, IPv6Cidr};
 
 pub fn routes() -> RouterIntoService<Body> {
     Router::new()
@@ -13,6 +17,13 @@ pub fn routes() -> RouterIntoService<Body> {
         .route("/key", get(egregious_decryption))
         .route("/v6/dest", get(egregious_encryption_v6))
         .route("/v6/key", get(egregious_decryption_v6))
+        .route("/cipher", get(cipher))
+        .route("/decipher", get(decipher))
+        .route("/subnet", get(subnet))
+        .route("/contains", get(contains))
+        .route("/v6/contains", get(contains_v6))
+        .route("/chain", get(chain))
+        .route("/chain/reverse", get(chain_reverse))
         .into_service()
 }
 
@@ -37,29 +48,384 @@ async fn egregious_encryption(
     Ok(result.into())
 }
 
+#[derive(Debug, Deserialize)]
+struct DecryptWarnParams {
+    /// Flag `from` and `to` being the same address, usually a client bug
+    #[serde(default)]
+    warn_identical: bool,
+}
+
+/// Response header carrying a warning about the decryption request
+const CIPHER_WARNING_HEADER: &str = "x-cipher-warning";
+
+/// Key produced when `from` and `to` are the same address
+const ZERO_KEY: &str = "0.0.0.0";
+
 async fn egregious_decryption(
     Query(DecryptParams { from, to }): Query<DecryptParams>,
-) -> Result<String> {
+    Query(DecryptWarnParams { warn_identical }): Query<DecryptWarnParams>,
+) -> Result<Response> {
     let from = IPv4Addr::try_from(from)?;
     let to = IPv4Addr::try_from(to)?;
-    let result = from.wrapping_sub(&to)?;
-    Ok(result.into())
+    let key = String::from(from.wrapping_sub(&to)?);
+
+    let identical = key == ZERO_KEY;
+    let mut response = key.into_response();
+    if warn_identical && identical {
+        response.headers_mut().insert(
+            CIPHER_WARNING_HEADER,
+            HeaderValue::from_static("identical-endpoints"),
+        );
+    }
+    Ok(response)
+}
+
+#[derive(Debug, Deserialize)]
+struct V6Params {
+    /// Reject IPv4-mapped and IPv4-compatible addresses
+    #[serde(default)]
+    strict: bool,
+}
+
+impl V6Params {
+    fn parse(&self, addr: String) -> Result<IPv6Addr> {
+        if self.strict {
+            IPv6Addr::try_from_strict(addr)
+        } else {
+            IPv6Addr::try_from(addr)
+        }
+    }
 }
 
 async fn egregious_encryption_v6(
     Query(EncryptParams { from, key }): Query<EncryptParams>,
-) -> Result<String> {
-    let from = IPv6Addr::try_from(from)?;
-    let key = IPv6Addr::try_from(key)?;
-    let result = from.xor(&key);
-    Ok(result.into())
+    Query(params): Query<V6Params>,
+) -> Result<Response> {
+    let (Ok(from), Ok(key)) = (params.parse(from), params.parse(key)) else {
+        return Ok(bad_request());
+    };
+    Ok(String::from(from.xor(&key)).into_response())
 }
 
 async fn egregious_decryption_v6(
     Query(DecryptParams { from, to }): Query<DecryptParams>,
-) -> Result<String> {
-    let from = IPv6Addr::try_from(from)?;
-    let to = IPv6Addr::try_from(to)?;
-    let result = from.xor(&to);
-    Ok(result.into())
+    Query(params): Query<V6Params>,
+) -> Result<Response> {
+    let (Ok(from), Ok(to)) = (params.parse(from), params.parse(to)) else {
+        return Ok(bad_request());
+    };
+    Ok(String::from(from.xor(&to)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct CipherParams {
+    text: String,
+    key: String,
+}
+
+async fn cipher(Query(CipherParams { text, key }): Query<CipherParams>) -> Result<Response> {
+    let Ok(key) = IPv4Addr::try_from(key) else {
+        return Ok(bad_request());
+    };
+    let ciphertext = key
+        .keystream_xor(text.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(ciphertext.into())?)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+async fn decipher(Query(CipherParams { text, key }): Query<CipherParams>) -> Result<Response> {
+    let (Ok(key), Some(ciphertext)) = (IPv4Addr::try_from(key), decode_hex(&text)) else {
+        return Ok(bad_request());
+    };
+    let Ok(plaintext) = String::from_utf8(key.keystream_xor(&ciphertext)) else {
+        return Ok(bad_request());
+    };
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(plaintext.into())?)
+}
+
+/// Largest subnet `/subnet` encrypts, a /24
+const MAX_SUBNET_HOSTS: u64 = 254;
+
+#[derive(Debug, Deserialize)]
+struct SubnetParams {
+    base: String,
+    key: String,
+}
+
+/// Every host of the `base` subnet encrypted with `key`, one per line
+async fn subnet(Query(SubnetParams { base, key }): Query<SubnetParams>) -> Result<Response> {
+    let (Ok(base), Ok(key)) = (IPv4Cidr::try_from(base), IPv4Addr::try_from(key)) else {
+        return Ok(bad_request());
+    };
+    if base.host_count() > MAX_SUBNET_HOSTS {
+        return Ok(bad_request());
+    }
+
+    let hosts = base
+        .hosts()
+        .map(|host| host.wrapping_add(&key).map(String::from))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(hosts.join("\n").into())?)
+}
+
+#[derive(Debug, Deserialize)]
+struct ContainsParams {
+    cidr: String,
+    addr: String,
+}
+
+fn contains_response(contains: bool) -> Result<Response> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(
+            serde_json::json!({ "contains": contains })
+                .to_string()
+                .into(),
+        )?)
+}
+
+/// Whether `addr` lies within the `cidr` network
+async fn contains(Query(ContainsParams { cidr, addr }): Query<ContainsParams>) -> Result<Response> {
+    let (Ok(cidr), Ok(addr)) = (IPv4Cidr::try_from(cidr), IPv4Addr::try_from(addr)) else {
+        return Ok(bad_request());
+    };
+    contains_response(cidr.contains(&addr))
+}
+
+async fn contains_v6(
+    Query(ContainsParams { cidr, addr }): Query<ContainsParams>,
+) -> Result<Response> {
+    let (Ok(cidr), Ok(addr)) = (IPv6Cidr::try_from(cidr), IPv6Addr::try_from(addr)) else {
+        return Ok(bad_request());
+    };
+    contains_response(cidr.contains(&addr))
+}
+
+fn parse_keys(keys: &str) -> Option<Vec<IPv4Addr>> {
+    keys.split(',')
+        .map(|key| IPv4Addr::try_from(key.trim().to_string()).ok())
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct ChainParams {
+    from: String,
+    /// Comma-separated keys, applied in order
+    keys: String,
+}
+
+/// `from` encrypted with each of `keys` in turn
+async fn chain(Query(ChainParams { from, keys }): Query<ChainParams>) -> Result<Response> {
+    let (Ok(from), Some(keys)) = (IPv4Addr::try_from(from), parse_keys(&keys)) else {
+        return Ok(bad_request());
+    };
+
+    let dest = keys
+        .iter()
+        .try_fold(from, |addr, key| addr.wrapping_add(key))?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(String::from(dest).into())?)
+}
+
+#[derive(Debug, Deserialize)]
+struct ReverseChainParams {
+    to: String,
+    keys: String,
+}
+
+/// The address `/chain` encrypted into `to`, undoing `keys` from last to first
+async fn chain_reverse(
+    Query(ReverseChainParams { to, keys }): Query<ReverseChainParams>,
+) -> Result<Response> {
+    let (Ok(to), Some(keys)) = (IPv4Addr::try_from(to), parse_keys(&keys)) else {
+        return Ok(bad_request());
+    };
+
+    let from = keys
+        .iter()
+        .rev()
+        .try_fold(to, |addr, key| key.wrapping_sub(&addr))?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(String::from(from).into())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    async fn get(uri: &str) -> (StatusCode, String) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = routes().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_contains() {
+        let (status, body) = get("/contains?cidr=10.0.0.0/24&addr=10.0.0.5").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"contains":true}"#);
+
+        let (_, body) = get("/contains?cidr=10.0.0.0/24&addr=10.0.1.5").await;
+        assert_eq!(body, r#"{"contains":false}"#);
+
+        let (status, body) = get("/v6/contains?cidr=2001:db8::/32&addr=2001:db8::5").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"contains":true}"#);
+
+        for uri in [
+            "/contains?cidr=10.0.0.0/33&addr=10.0.0.5",
+            "/contains?cidr=10.0.0.0/24&addr=10.0.0",
+            "/contains?cidr=10.0.0.0/24",
+            "/v6/contains?cidr=2001:db8::/32&addr=10.0.0.5",
+        ] {
+            let (status, _) = get(uri).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cipher() {
+        let (status, body) = get("/cipher?text=HELLO&key=1.2.3.4").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "49474f484e");
+    }
+
+    #[tokio::test]
+    async fn test_cipher_round_trip() {
+        let (_, ciphertext) =
+            get("/cipher?text=Hello%2C%20%E2%9D%84%EF%B8%8F&key=10.0.42.255").await;
+        let (status, plaintext) =
+            get(&format!("/decipher?text={ciphertext}&key=10.0.42.255")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(plaintext, "Hello, ❄️");
+    }
+
+    #[tokio::test]
+    async fn test_cipher_invalid_key() {
+        let (status, _) = get("/cipher?text=HELLO&key=1.2.3").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = get("/decipher?text=49&key=1.2.3.256").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_subnet() {
+        let (status, body) = get("/subnet?base=10.0.0.0/29&key=0.0.0.1").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body.lines().collect::<Vec<_>>(),
+            ["10.0.0.2", "10.0.0.3", "10.0.0.4", "10.0.0.5", "10.0.0.6", "10.0.0.7"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subnet_invalid() {
+        for uri in [
+            "/subnet?base=10.0.0.0/16&key=0.0.0.1",
+            "/subnet?base=10.0.0.0&key=0.0.0.1",
+            "/subnet?base=10.0.0.0/29&key=1",
+        ] {
+            let (status, _) = get(uri).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_v6_strict() {
+        let (status, body) = get("/v6/dest?from=::ffff:1.2.3.4&key=::1").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "::ffff:1.2.3.5");
+
+        let (status, _) = get("/v6/dest?from=::ffff:1.2.3.4&key=::1&strict=true").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = get("/v6/key?from=fe80::1&to=::ffff:1.2.3.4&strict=true").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = get("/v6/dest?from=fe80::1&key=::1&strict=true").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "fe80::");
+    }
+
+    #[tokio::test]
+    async fn test_chain() {
+        let (status, body) = get("/chain?from=10.0.0.0&keys=1.2.3.4,250.0.0.1").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "5.2.3.5");
+    }
+
+    #[tokio::test]
+    async fn test_chain_round_trip() {
+        let keys = "1.2.3.4,255.255.255.255,128.0.7.200";
+        let (_, dest) = get(&format!("/chain?from=10.0.42.255&keys={keys}")).await;
+        let (status, from) = get(&format!("/chain/reverse?to={dest}&keys={keys}")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(from, "10.0.42.255");
+    }
+
+    #[tokio::test]
+    async fn test_chain_invalid_key() {
+        for uri in [
+            "/chain?from=10.0.0.0&keys=1.2.3.4,1.2.3",
+            "/chain?from=10.0.0.0&keys=",
+            "/chain?from=10.0.0&keys=1.2.3.4",
+            "/chain/reverse?to=10.0.0.0&keys=1.2.3.4,,1.2.3.4",
+        ] {
+            let (status, _) = get(uri).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_identical_endpoints_warning() {
+        let request = Request::get("/key?from=10.0.0.1&to=10.0.0.1&warn_identical=true")
+            .body(Body::empty())
+            .unwrap();
+        let response = routes().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[CIPHER_WARNING_HEADER],
+            "identical-endpoints"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "0.0.0.0");
+
+        for uri in [
+            "/key?from=10.0.0.1&to=10.0.0.1",
+            "/key?from=10.0.0.1&to=10.0.0.2&warn_identical=true",
+        ] {
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            let response = routes().oneshot(request).await.unwrap();
+            assert!(!response.headers().contains_key(CIPHER_WARNING_HEADER));
+        }
+    }
 }
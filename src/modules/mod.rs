@@ -6,3 +6,4 @@ pub mod day_sixteen;
 pub mod day_twelve;
 pub mod day_twenty_three;
 pub mod day_two;
+pub mod version;
@@ -0,0 +1,64 @@
+use axum::body::Body;
+use axum::routing::{get, RouterIntoService};
+use axum::{Json, Router};
+use serde::Serialize;
+
+pub fn routes() -> RouterIntoService<Body> {
+    Router::new().route("/", get(version)).into_service()
+}
+
+/// Placeholder for build-time values the build environment did not provide
+const UNKNOWN: &str = "unknown";
+
+#[derive(Debug, Serialize)]
+struct VersionInfo {
+    version: &'static str,
+    git_sha: &'static str,
+    build_timestamp: &'static str,
+}
+
+impl VersionInfo {
+    /// Reads the build metadata baked into the binary at compile time
+    const fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: match option_env!("VERGEN_GIT_SHA") {
+                Some(sha) => sha,
+                None => UNKNOWN,
+            },
+            build_timestamp: match option_env!("VERGEN_BUILD_TIMESTAMP") {
+                Some(timestamp) => timestamp,
+                None => UNKNOWN,
+            },
+        }
+    }
+}
+
+#[tracing::instrument]
+async fn version() -> Json<VersionInfo> {
+    Json(VersionInfo::current())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_version() {
+        let response = routes()
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let info: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert!(info["git_sha"].is_string());
+        assert!(info["build_timestamp"].is_string());
+    }
+}
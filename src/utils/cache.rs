@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Short-lived in-memory cache, dropped wholesale whenever its generation is bumped
+#[derive(Debug)]
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    generation: AtomicU64,
+    entries: Mutex<CacheEntries<K, V>>,
+}
+
+#[derive(Debug)]
+struct CacheEntries<K, V> {
+    generation: u64,
+    values: HashMap<K, (Instant, V)>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            generation: AtomicU64::new(0),
+            entries: Mutex::new(CacheEntries {
+                generation: 0,
+                values: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Current generation, to be captured before computing a value to insert
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Invalidates every cached value
+    pub fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().ok()?;
+        let generation = self.generation();
+        if entries.generation != generation {
+            entries.generation = generation;
+            entries.values.clear();
+        }
+
+        let (inserted_at, value) = entries.values.get(key)?;
+        if inserted_at.elapsed() >= self.ttl {
+            entries.values.remove(key);
+            return None;
+        }
+        Some(value.clone())
+    }
+
+    /// Caches `value`, unless the cache was invalidated since `generation` was read
+    pub fn insert(&self, key: K, value: V, generation: u64) {
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        if generation != self.generation() {
+            return;
+        }
+        if entries.generation != generation {
+            entries.generation = generation;
+            entries.values.clear();
+        }
+        entries.values.insert(key, (Instant::now(), value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_hit() {
+        let cache = TtlCache::new(Duration::from_secs(2));
+        cache.insert(1, "one", cache.generation());
+        assert_eq!(cache.get(&1), Some("one"));
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[test]
+    fn test_cache_invalidate() {
+        let cache = TtlCache::new(Duration::from_secs(2));
+        cache.insert(1, "one", cache.generation());
+        cache.invalidate();
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn test_cache_ignores_stale_insert() {
+        let cache = TtlCache::new(Duration::from_secs(2));
+        let generation = cache.generation();
+        cache.invalidate();
+        cache.insert(1, "one", generation);
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cache_expires() {
+        let cache = TtlCache::new(Duration::from_secs(2));
+        cache.insert(1, "one", cache.generation());
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(cache.get(&1), Some("one"));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(cache.get(&1), None);
+    }
+}
@@ -1,24 +1,159 @@
 use core::fmt;
+use std::collections::HashMap;
 use std::fmt::Display;
+use std::sync::Arc;
 
-use axum::extract::{FromRequest, Request};
-use axum::http::header::CONTENT_TYPE;
+use axum::extract::{FromRef, FromRequest, Request};
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::HeaderMap;
 use axum::response::Response;
-use axum::RequestExt;
 use cargo_manifest::Manifest;
-use serde::Deserialize;
+use serde::Serialize;
 
 use crate::utils::error_responses::{
-    invalid_manifest, magic_keyword_not_provided, no_content, unsupported_content_type,
+    invalid_manifest, magic_keyword_not_provided, payload_too_large, unsupported_content_type,
 };
 
-#[serde_with::serde_as]
-#[derive(Deserialize, Debug, Clone)]
+/// Environment variable holding the maximum manifest size in bytes
+pub const MANIFEST_MAX_BYTES_ENV: &str = "MANIFEST_MAX_BYTES";
+
+const DEFAULT_MANIFEST_MAX_BYTES: usize = 1024 * 1024;
+
+fn manifest_max_bytes() -> usize {
+    std::env::var(MANIFEST_MAX_BYTES_ENV)
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_MANIFEST_MAX_BYTES)
+}
+
+/// Environment variable holding the comma-separated file extensions accepted
+/// when the manifest format is inferred from its filename
+pub const MANIFEST_EXTENSIONS_ENV: &str = "MANIFEST_ALLOWED_EXTENSIONS";
+
+const DEFAULT_MANIFEST_EXTENSIONS: &str = "toml,json,yaml,yml";
+
+/// Lowercased, non-empty extensions of a comma-separated list
+fn parse_extensions(extensions: &str) -> Arc<[String]> {
+    extensions
+        .split(',')
+        .map(|extension| extension.trim().to_lowercase())
+        .filter(|extension| !extension.is_empty())
+        .collect()
+}
+
+fn allowed_extensions() -> Arc<[String]> {
+    parse_extensions(
+        &std::env::var(MANIFEST_EXTENSIONS_ENV)
+            .unwrap_or_else(|_| DEFAULT_MANIFEST_EXTENSIONS.to_string()),
+    )
+}
+
+/// Environment variable holding the quantity given to orders that omit one
+pub const MANIFEST_DEFAULT_QUANTITY_ENV: &str = "MANIFEST_DEFAULT_QUANTITY";
+
+/// Configured quantity for orders without one, if any; unconfigured, such
+/// orders are skipped when walking the manifest
+fn default_quantity() -> Option<u32> {
+    std::env::var(MANIFEST_DEFAULT_QUANTITY_ENV)
+        .ok()
+        .and_then(|quantity| quantity.trim().parse().ok())
+}
+
+/// Manifest settings resolved once when the routes are built, so parsing a
+/// manifest never reads the environment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestConfig {
+    /// Largest manifest body accepted, in bytes
+    pub max_bytes: usize,
+    /// Lowercase extensions a filename may have for its format to be inferred
+    pub allowed_extensions: Arc<[String]>,
+    /// Quantity for orders that omit one, `None` to skip such orders
+    pub default_quantity: Option<u32>,
+}
+
+impl ManifestConfig {
+    pub fn from_env() -> Self {
+        Self {
+            max_bytes: manifest_max_bytes(),
+            allowed_extensions: allowed_extensions(),
+            default_quantity: default_quantity(),
+        }
+    }
+}
+
+impl Default for ManifestConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MANIFEST_MAX_BYTES,
+            allowed_extensions: parse_extensions(DEFAULT_MANIFEST_EXTENSIONS),
+            default_quantity: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl ManifestFormat {
+    fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type {
+            "application/toml" => Some(Self::Toml),
+            "application/json" => Some(Self::Json),
+            "application/yaml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Toml => "application/toml",
+            Self::Json => "application/json",
+            Self::Yaml => "application/yaml",
+        }
+    }
+}
+
+/// Filename from a `Content-Disposition` header, e.g. `attachment; filename="Cargo.toml"`
+fn filename_hint(headers: &HeaderMap) -> Option<String> {
+    let disposition = headers.get(CONTENT_DISPOSITION)?.to_str().ok()?;
+    disposition
+        .split(';')
+        .filter_map(|param| param.trim().strip_prefix("filename="))
+        .map(|filename| filename.trim_matches('"').to_string())
+        .next()
+}
+
+fn format_from_filename(filename: &str, allowed: &[String]) -> Option<ManifestFormat> {
+    let extension = std::path::Path::new(filename)
+        .extension()?
+        .to_str()?
+        .to_lowercase();
+    if !allowed.contains(&extension) {
+        return None;
+    }
+    ManifestFormat::from_extension(&extension)
+}
+
+/// Only serialized; orders are read from manifests by `metadata_from`, which
+/// applies `ManifestConfig::default_quantity`
+#[derive(Serialize, Debug, Clone)]
 pub struct Order {
     #[serde(rename = "item")]
     pub item: String,
-    #[serde_as(deserialize_as = "serde_with::DefaultOnError")]
-    #[serde(default, rename = "quantity")]
+    #[serde(rename = "quantity")]
     pub quantity: u32,
 }
 
@@ -34,20 +169,78 @@ impl Display for Order {
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone)]
 pub struct Metadata {
-    #[serde(default, rename = "orders")]
+    #[serde(rename = "orders")]
     pub orders: Vec<Order>,
+    /// Problems that did not stop the manifest from parsing, e.g. skipped orders
+    #[serde(skip)]
+    pub warnings: Vec<String>,
 }
 
 impl Metadata {
     pub fn new(orders: Vec<Order>) -> Self {
-        Self { orders }
+        Self {
+            orders,
+            warnings: vec![],
+        }
     }
 
     pub fn add_order(&mut self, item: String, quantity: u32) {
         self.orders.push(Order::new(item, quantity));
     }
+
+    /// Warns about orders that parsed but are probably mistakes, reporting a
+    /// repeated item once at its first order
+    fn lint(mut self) -> Self {
+        let mut counts = HashMap::<&str, usize>::new();
+        for order in &self.orders {
+            *counts.entry(&order.item).or_default() += 1;
+        }
+        for order in &self.orders {
+            if order.quantity == 0 {
+                self.warnings.push(format!("{}: quantity is 0", order.item));
+            }
+            if counts
+                .remove(order.item.as_str())
+                .is_some_and(|count| count > 1)
+            {
+                self.warnings
+                    .push(format!("{}: ordered more than once", order.item));
+            }
+        }
+        self
+    }
+
+    /// Combines orders for the same item into one, summing their quantities
+    /// and keeping the position of the item's first order
+    pub fn merged(self) -> Self {
+        let mut merged: Vec<Order> = vec![];
+        // position of each item's order in `merged`
+        let mut positions = HashMap::<String, usize>::new();
+        for order in self.orders {
+            match positions.get(&order.item) {
+                Some(&i) => merged[i].quantity = merged[i].quantity.saturating_add(order.quantity),
+                None => {
+                    positions.insert(order.item.clone(), merged.len());
+                    merged.push(order);
+                }
+            }
+        }
+        Self {
+            orders: merged,
+            warnings: self.warnings,
+        }
+    }
+
+    /// Serializes the orders as `orders = [{ item, quantity }, ...]` in `format`
+    pub fn serialize(&self, format: ManifestFormat) -> anyhow::Result<String> {
+        Ok(match format {
+            ManifestFormat::Toml => toml::to_string(self)?,
+            ManifestFormat::Json => serde_json::to_string(self)?,
+            ManifestFormat::Yaml => serde_yaml::to_string(self)?,
+        })
+    }
 }
 
 impl Display for Metadata {
@@ -65,37 +258,53 @@ impl Display for Metadata {
 }
 
 #[async_trait::async_trait]
-impl<S> FromRequest<S> for Metadata {
+impl<S> FromRequest<S> for Metadata
+where
+    S: Send + Sync,
+    ManifestConfig: FromRef<S>,
+{
     type Rejection = Response;
 
-    async fn from_request(req: Request, _state: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let config = ManifestConfig::from_ref(state);
         let headers = req.headers().clone();
         let Some(content_type) = headers.get(CONTENT_TYPE) else {
             return Err(unsupported_content_type());
         };
-        let body: String = req.extract().await.map_err(|_| invalid_manifest())?;
-
-        let parsed_manifest: Manifest;
-
-        match content_type.to_str() {
-            Ok(content_type) => match content_type {
-                "application/toml" => match toml::from_str(&body) {
-                    Ok(manifest) => parsed_manifest = manifest,
-                    Err(_) => return Err(invalid_manifest()),
-                },
-                "application/json" => match serde_json::from_str(&body) {
-                    Ok(manifest) => parsed_manifest = manifest,
-                    Err(_) => return Err(invalid_manifest()),
-                },
-                "application/yaml" => match serde_yaml::from_str(&body) {
-                    Ok(manifest) => parsed_manifest = manifest,
-                    Err(_) => return Err(invalid_manifest()),
-                },
-                _ => return Err(unsupported_content_type()),
-            },
-            Err(_) => return Err(unsupported_content_type()),
+
+        let max_bytes = config.max_bytes;
+        let declared_length = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse::<usize>().ok());
+        if declared_length.is_some_and(|length| length > max_bytes) {
+            return Err(payload_too_large());
         }
 
+        // bodies without a (truthful) length only fail once the limit is hit
+        let body = axum::body::to_bytes(req.into_body(), max_bytes)
+            .await
+            .map_err(|_| payload_too_large())?;
+        let body = String::from_utf8(body.to_vec()).map_err(|_| invalid_manifest())?;
+
+        let format = match content_type.to_str() {
+            Ok("application/octet-stream") => filename_hint(&headers)
+                .and_then(|filename| format_from_filename(&filename, &config.allowed_extensions)),
+            Ok(content_type) => ManifestFormat::from_content_type(content_type),
+            Err(_) => None,
+        };
+
+        let parsed_manifest: Manifest = match format {
+            Some(ManifestFormat::Toml) => toml::from_str(&body).map_err(|_| invalid_manifest())?,
+            Some(ManifestFormat::Json) => {
+                serde_json::from_str(&body).map_err(|_| invalid_manifest())?
+            }
+            Some(ManifestFormat::Yaml) => {
+                serde_yaml::from_str(&body).map_err(|_| invalid_manifest())?
+            }
+            None => return Err(unsupported_content_type()),
+        };
+
         let Some(package) = parsed_manifest.package else {
             return Err(magic_keyword_not_provided());
         };
@@ -108,39 +317,156 @@ impl<S> FromRequest<S> for Metadata {
             return Err(magic_keyword_not_provided());
         }
 
-        let Some(metadata) = package.metadata else {
-            return Err(no_content());
+        // manifests without (usable) orders are valid, they just order nothing
+        let Some(cargo_manifest::Value::Table(metadata)) = package.metadata else {
+            return Ok(Metadata::new(vec![]));
         };
 
-        let cargo_manifest::Value::Table(metadata) = metadata else {
-            return Err(no_content());
-        };
+        Ok(metadata
+            .get("orders")
+            .and_then(|orders| metadata_from(orders, config.default_quantity))
+            .unwrap_or_else(|| Metadata::new(vec![]))
+            .lint())
+    }
+}
 
-        let Some(cargo_manifest::Value::Array(orders)) = metadata.get("orders") else {
-            return Err(no_content());
-        };
+/// Orders authored either as an array of `{ item, quantity }` tables or as a
+/// table of `item = quantity`, skipping entries that are malformed. Orders
+/// omitting their quantity get `default_quantity`, or are skipped without one
+fn metadata_from(
+    orders: &cargo_manifest::Value,
+    default_quantity: Option<u32>,
+) -> Option<Metadata> {
+    let quantity = |quantity: Option<&cargo_manifest::Value>| match quantity {
+        Some(cargo_manifest::Value::Integer(quantity)) => u32::try_from(*quantity).ok(),
+        None => default_quantity,
+        _ => None,
+    };
 
-        let mut metadata = Metadata::new(vec![]);
-        for order in orders {
-            let cargo_manifest::Value::Table(order) = order else {
-                continue;
-            };
+    let mut metadata = Metadata::new(vec![]);
+    match orders {
+        cargo_manifest::Value::Array(orders) => {
+            for (i, order) in orders.iter().enumerate() {
+                let cargo_manifest::Value::Table(order) = order else {
+                    metadata
+                        .warnings
+                        .push(format!("order {}: skipped, not a table", i + 1));
+                    continue;
+                };
 
-            let Some(cargo_manifest::Value::String(item)) = order.get("item") else {
-                continue;
-            };
+                let Some(cargo_manifest::Value::String(item)) = order.get("item") else {
+                    metadata
+                        .warnings
+                        .push(format!("order {}: skipped, invalid item", i + 1));
+                    continue;
+                };
 
-            let Some(cargo_manifest::Value::Integer(quantity)) = order.get("quantity") else {
-                continue;
-            };
+                let Some(quantity) = quantity(order.get("quantity")) else {
+                    metadata
+                        .warnings
+                        .push(format!("{item}: skipped, invalid quantity"));
+                    continue;
+                };
 
-            let Ok(quantity) = u32::try_from(*quantity) else {
-                continue;
-            };
+                metadata.add_order(item.clone(), quantity);
+            }
+        }
+        cargo_manifest::Value::Table(orders) => {
+            for (item, value) in orders {
+                let Some(quantity) = quantity(Some(value)) else {
+                    metadata
+                        .warnings
+                        .push(format!("{item}: skipped, invalid quantity"));
+                    continue;
+                };
 
-            metadata.add_order(item.clone(), quantity);
+                metadata.add_order(item.clone(), quantity);
+            }
         }
+        _ => return None,
+    }
+
+    Some(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orders(toml: &str) -> cargo_manifest::Value {
+        toml::from_str::<cargo_manifest::Value>(toml).unwrap()["orders"].clone()
+    }
+
+    const MISSING_QUANTITY: &str = r#"[[orders]]
+item = "Toy car"
+
+[[orders]]
+item = "Lego brick"
+quantity = 230
+"#;
+
+    #[test]
+    fn test_missing_quantity_skipped_without_default() {
+        let metadata = metadata_from(&orders(MISSING_QUANTITY), None).unwrap();
+        assert_eq!(metadata.to_string(), "Lego brick: 230");
+    }
+
+    #[test]
+    fn test_missing_quantity_uses_default() {
+        let metadata = metadata_from(&orders(MISSING_QUANTITY), Some(1)).unwrap();
+        assert_eq!(metadata.to_string(), "Toy car: 1\nLego brick: 230");
+    }
+
+    #[test]
+    fn test_lint_warnings() {
+        let mut metadata = metadata_from(
+            &orders(
+                r#"[[orders]]
+item = "Toy car"
+quantity = 0
+
+[[orders]]
+item = "Lego brick"
+quantity = "many"
+
+[[orders]]
+item = "Toy car"
+quantity = 2
+"#,
+            ),
+            None,
+        )
+        .unwrap()
+        .lint();
+        metadata.warnings.sort();
+        assert_eq!(
+            metadata.warnings,
+            vec![
+                "Lego brick: skipped, invalid quantity",
+                "Toy car: ordered more than once",
+                "Toy car: quantity is 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merged_keeps_first_seen_order() {
+        let orders = [("b", 1), ("a", 2), ("b", 3), ("c", 4), ("a", u32::MAX)]
+            .map(|(item, quantity)| Order::new(item.to_string(), quantity))
+            .to_vec();
+        let metadata = Metadata::new(orders).merged();
+        assert_eq!(metadata.to_string(), format!("b: 4\na: {}\nc: 4", u32::MAX));
+    }
 
-        Ok(metadata)
+    #[test]
+    fn test_lint_reports_repeats_in_first_seen_order() {
+        let orders = ["b", "a", "b", "c", "a", "b"]
+            .map(|item| Order::new(item.to_string(), 1))
+            .to_vec();
+        let metadata = Metadata::new(orders).lint();
+        assert_eq!(
+            metadata.warnings,
+            vec!["b: ordered more than once", "a: ordered more than once"]
+        );
     }
 }
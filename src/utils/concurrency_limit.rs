@@ -0,0 +1,90 @@
+use axum::body::Body;
+use axum::error_handling::HandleErrorLayer;
+use axum::http::StatusCode;
+use axum::routing::RouterIntoService;
+use axum::{BoxError, Router};
+use tower::ServiceBuilder;
+
+/// Environment variable capping the requests a service handles at once
+pub const MAX_CONCURRENCY_ENV: &str = "MAX_CONCURRENCY";
+
+const DEFAULT_MAX_CONCURRENCY: usize = 256;
+
+pub fn max_concurrency() -> usize {
+    std::env::var(MAX_CONCURRENCY_ENV)
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .filter(|&max| max > 0)
+        .unwrap_or(DEFAULT_MAX_CONCURRENCY)
+}
+
+/// Wraps `service` so at most `max` requests are in flight, shedding the rest
+/// with a 503 instead of queueing them
+pub fn limit_concurrency(service: RouterIntoService<Body>, max: usize) -> RouterIntoService<Body> {
+    let service = ServiceBuilder::new()
+        .layer(HandleErrorLayer::new(|_: BoxError| async {
+            StatusCode::SERVICE_UNAVAILABLE
+        }))
+        .load_shed()
+        .concurrency_limit(max)
+        .service(service);
+    Router::new().fallback_service(service).into_service()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use axum::extract::Request;
+    use axum::routing::get;
+    use tokio::sync::Semaphore;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_overflow_is_shed() {
+        // requests block until the test hands out a permit
+        let gate = Arc::new(Semaphore::new(0));
+        // and hand one back here once they are inside the handler
+        let entered = Arc::new(Semaphore::new(0));
+        let (handler_gate, handler_entered) = (gate.clone(), entered.clone());
+        let router = Router::new()
+            .route(
+                "/",
+                get(|| async move {
+                    handler_entered.add_permits(1);
+                    handler_gate.acquire().await.unwrap().forget();
+                    "ok"
+                }),
+            )
+            .into_service();
+        let service = limit_concurrency(router, 2);
+
+        let mut in_flight = vec![];
+        for _ in 0..2 {
+            let service = service.clone();
+            in_flight.push(tokio::spawn(async move {
+                let request = Request::get("/").body(Body::empty()).unwrap();
+                service.oneshot(request).await.unwrap().status()
+            }));
+        }
+        entered.acquire_many(2).await.unwrap().forget();
+
+        let request = Request::get("/").body(Body::empty()).unwrap();
+        let overflow = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(overflow.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        gate.add_permits(2);
+        for request in in_flight {
+            assert_eq!(request.await.unwrap(), StatusCode::OK);
+        }
+
+        gate.add_permits(1);
+        let request = Request::get("/").body(Body::empty()).unwrap();
+        assert_eq!(
+            service.oneshot(request).await.unwrap().status(),
+            StatusCode::OK
+        );
+    }
+}
@@ -2,25 +2,146 @@ use anyhow::{bail, Result};
 use rand::rngs::StdRng;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::ops::Deref;
 
+/// Rows and columns of a board built with `Connect4::default`
 pub const BOARD_SIZE: usize = 4;
 
+/// Most cells a board may have, so a position fits in one bit per cell
+pub const MAX_CELLS: usize = u128::BITS as usize;
+
+/// Rows from top to bottom, every row as wide as the board
+type Board = Vec<Vec<Cell>>;
+
+/// Milk and cookie pieces of a board, one bit per cell row by row, with the
+/// player to move and the depth left, keying the minimax memo
+type MemoKey = (u128, u128, Player, usize);
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Connect4 {
-    board: [[Cell; BOARD_SIZE]; BOARD_SIZE],
+    board: Board,
+    win_rule: WinRule,
+    /// Moves played since the last reset, oldest first
+    moves: Vec<Move>,
+}
+
+/// A single piece dropped by `play`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Move {
+    pub player: Player,
+    /// 0-based column the piece was dropped into
+    pub column: usize,
+    /// 0-based row, counted from the top, the piece came to rest in
+    pub row: usize,
+}
+
+/// Condition a player has to meet on the board to win
+// alternative rules are only picked by experiments and tests for now
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WinRule {
+    /// `n` in a row horizontally, vertically or diagonally
+    ConnectN(usize),
+    /// A filled 2x2 block anywhere on the board
+    Square2x2,
+    /// All four corners of the board
+    FourCorners,
+}
+
+impl Default for WinRule {
+    fn default() -> Self {
+        Self::ConnectN(4)
+    }
+}
+
+/// How hard the computer tries when suggesting a move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    /// Only spots immediate wins, otherwise picks any near-best move at random
+    Easy,
+    /// Looks a few moves ahead
+    #[default]
+    Medium,
+    /// Searches as deep as `Connect4::search_depth` allows
+    Hard,
+}
+
+impl Difficulty {
+    /// Plies searched on `game`
+    fn depth(&self, game: &Connect4) -> usize {
+        match self {
+            Self::Easy => 1,
+            Self::Medium => 3,
+            Self::Hard => game.search_depth(),
+        }
+    }
+}
+
+/// Most cells a board may have for `search_depth` to search it to the end
+pub const FULL_SEARCH_CELLS: usize = 16;
+
+/// Plies `search_depth` looks ahead on boards above `FULL_SEARCH_CELLS`
+pub const CAPPED_SEARCH_DEPTH: usize = 6;
+
+/// Score by which an easy move may trail the best one and still be picked
+const EASY_SLACK: i32 = 2;
+
+/// Width and height of one board cell in the SVG rendering
+const SVG_CELL: usize = 40;
+
+/// Row/column steps for the right, down, down-right and down-left directions
+const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+impl Default for Connect4 {
+    fn default() -> Self {
+        Self::with_size(BOARD_SIZE, BOARD_SIZE)
+    }
 }
 
 impl Connect4 {
-    pub fn new() -> Self {
+    /// Empty board `rows` high and `cols` wide, e.g. 6x7 for classic Connect-4
+    ///
+    /// Panics if the board would have more than `MAX_CELLS` cells
+    pub fn with_size(rows: usize, cols: usize) -> Self {
+        assert!(
+            rows * cols <= MAX_CELLS,
+            "a {rows}x{cols} board has more than {MAX_CELLS} cells"
+        );
         Self {
-            board: [[Cell::default(); BOARD_SIZE]; BOARD_SIZE],
+            board: vec![vec![Cell::default(); cols]; rows],
+            win_rule: WinRule::default(),
+            moves: vec![],
         }
     }
 
-    pub fn random(rng: &mut StdRng) -> Self {
-        let mut connect4 = Self::new();
+    pub fn rows(&self) -> usize {
+        self.board.len()
+    }
+
+    pub fn cols(&self) -> usize {
+        self.board.first().map_or(0, Vec::len)
+    }
+
+    /// Pieces in a row needed to win, `None` for rules that aren't about lines
+    pub fn win_length(&self) -> Option<usize> {
+        match self.win_rule {
+            WinRule::ConnectN(n) => Some(n),
+            WinRule::Square2x2 | WinRule::FourCorners => None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_win_rule(mut self, win_rule: WinRule) -> Self {
+        self.win_rule = win_rule;
+        self
+    }
+
+    /// Board `rows` high and `cols` wide with every cell filled at random
+    pub fn random(rows: usize, cols: usize, rng: &mut StdRng) -> Self {
+        let mut connect4 = Self::with_size(rows, cols);
         for row in connect4.board.iter_mut() {
             for cell in row.iter_mut() {
                 let player = if rng.gen::<bool>() {
@@ -35,84 +156,381 @@ impl Connect4 {
     }
 
     pub fn play(&mut self, player: Player, column: usize) -> Result<()> {
-        if column >= BOARD_SIZE {
+        if column >= self.cols() {
             bail!("Invalid column");
         }
-        for row in self.board.iter_mut().rev() {
-            if row[column].is_none() {
-                row[column] = player.into();
+        for (row, cells) in self.board.iter_mut().enumerate().rev() {
+            if cells[column].is_none() {
+                cells[column] = player.into();
+                self.moves.push(Move {
+                    player,
+                    column,
+                    row,
+                });
                 return Ok(());
             }
         }
         bail!("Column full");
     }
 
+    /// Moves played since the last reset, oldest first
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// Takes back the last move, lifting its piece off the top of its column
+    pub fn undo(&mut self) -> Result<Move> {
+        let Some(last) = self.moves.pop() else {
+            bail!("No moves to undo");
+        };
+        self.board[last.row][last.column] = Cell::default();
+        Ok(last)
+    }
+
+    /// Rebuilds the board from the first `n` moves played, dropping the rest
+    pub fn rewind(&mut self, n: usize) -> Result<()> {
+        if n > self.moves.len() {
+            bail!("Only {} moves to rewind", self.moves.len());
+        }
+        let moves = self.moves[..n].to_vec();
+        self.reset();
+        for Move { player, column, .. } in moves {
+            self.play(player, column)?;
+        }
+        Ok(())
+    }
+
     pub fn board_full(&self) -> bool {
         self.board
             .iter()
             .all(|row| row.iter().all(|cell| cell.is_some()))
     }
 
+    /// Number of pieces on the board
+    pub fn move_count(&self) -> usize {
+        self.board
+            .iter()
+            .map(|row| row.iter().filter(|cell| cell.is_some()).count())
+            .sum()
+    }
+
     pub fn column_full(&self, column: usize) -> bool {
-        self.board[0][column].is_some()
+        self.board
+            .first()
+            .and_then(|row| row.get(column))
+            .is_none_or(|cell| cell.is_some())
     }
 
-    fn check_winner(
-        &self,
-        row: usize,
-        col: usize,
-        row_delta: isize,
-        col_delta: isize,
-    ) -> Option<Player> {
-        let cell = self.board.get(row).and_then(|row| row.get(col))?;
-        if cell.is_none() {
+    /// Returns the player owning every one of `cells`, if any
+    fn owner(&self, cells: &[(usize, usize)]) -> Option<Player> {
+        let (first, rest) = cells.split_first()?;
+        let player = self.board.get(first.0)?.get(first.1)?.0?;
+        for &(row, col) in rest {
+            if self.board.get(row)?.get(col)?.0 != Some(player) {
+                return None;
+            }
+        }
+        Some(player)
+    }
+
+    fn connect_n(&self, n: usize) -> Option<(Player, Vec<(usize, usize)>)> {
+        if n == 0 {
             return None;
         }
-        for i in 1..4 {
-            let row = row.checked_add_signed(row_delta * i)?;
-            let col = col.checked_add_signed(col_delta * i)?;
-            if row >= BOARD_SIZE || col >= BOARD_SIZE {
-                return None;
+        for row in 0..self.rows() {
+            for col in 0..self.cols() {
+                for (row_delta, col_delta) in DIRECTIONS {
+                    let cell = |i: usize| {
+                        Some((
+                            row.checked_add_signed(row_delta * i as isize)?,
+                            col.checked_add_signed(col_delta * i as isize)?,
+                        ))
+                    };
+                    // checked cell by cell, as this runs at every search node
+                    let piece = |i| {
+                        let (row, col) = cell(i)?;
+                        self.board.get(row)?.get(col)?.0
+                    };
+                    let Some(player) = piece(0) else {
+                        continue;
+                    };
+                    if (1..n).all(|i| piece(i) == Some(player)) {
+                        let line = (0..n).filter_map(cell).collect();
+                        return Some((player, line));
+                    }
+                }
             }
-            if self.board.get(row).and_then(|row| row.get(col)) != Some(cell) {
-                return None;
+        }
+        None
+    }
+
+    fn square_2x2(&self) -> Option<(Player, Vec<(usize, usize)>)> {
+        for row in 0..self.rows().saturating_sub(1) {
+            for col in 0..self.cols().saturating_sub(1) {
+                let square = vec![
+                    (row, col),
+                    (row, col + 1),
+                    (row + 1, col),
+                    (row + 1, col + 1),
+                ];
+                if let Some(player) = self.owner(&square) {
+                    return Some((player, square));
+                }
             }
         }
-        Some(cell.unwrap())
+        None
+    }
+
+    fn four_corners(&self) -> Option<(Player, Vec<(usize, usize)>)> {
+        let (last_row, last_col) = (self.rows().checked_sub(1)?, self.cols().checked_sub(1)?);
+        let corners = vec![(0, 0), (0, last_col), (last_row, 0), (last_row, last_col)];
+        self.owner(&corners).map(|player| (player, corners))
+    }
+
+    /// Returns the winner along with the `(row, column)` cells satisfying the win rule
+    pub fn winning_line(&self) -> Option<(Player, Vec<(usize, usize)>)> {
+        match self.win_rule {
+            WinRule::ConnectN(n) => self.connect_n(n),
+            WinRule::Square2x2 => self.square_2x2(),
+            WinRule::FourCorners => self.four_corners(),
+        }
     }
 
     pub fn winner(&self) -> Option<Player> {
-        for row in 0..BOARD_SIZE {
-            if let Some(player) = self.check_winner(row, 0, 0, 1) {
-                return Some(player);
-            }
+        self.winning_line().map(|(player, _)| player)
+    }
+
+    fn empty_cells(&self) -> usize {
+        self.rows() * self.cols() - self.move_count()
+    }
+
+    /// Minimax score for `player` dropping a piece into each column, searching
+    /// `depth` plies with both players alternating from then on.
+    ///
+    /// Wins score positive and losses negative, larger the sooner they happen;
+    /// draws and positions beyond the search depth score 0. Full or otherwise
+    /// illegal columns are `None`, as is every column once the game is over.
+    pub fn evaluate_columns(&self, player: Player, depth: usize) -> Vec<Option<i32>> {
+        if self.winner().is_some() {
+            return vec![None; self.cols()];
         }
+        let mut memo = HashMap::new();
+        let mut game = self.clone();
+        (0..self.cols())
+            .map(|column| game.score_move(player, column, depth, &mut memo))
+            .collect()
+    }
 
-        for col in 0..BOARD_SIZE {
-            if let Some(player) = self.check_winner(0, col, 1, 0) {
-                return Some(player);
-            }
+    /// Searches until the board is full
+    pub fn full_depth(&self) -> usize {
+        self.empty_cells()
+    }
+
+    /// Deepest search worth running on this board: the whole game up to
+    /// `FULL_SEARCH_CELLS` cells, `CAPPED_SEARCH_DEPTH` plies beyond that, with
+    /// the positions left at the cut-off scored as draws
+    pub fn search_depth(&self) -> usize {
+        if self.rows() * self.cols() <= FULL_SEARCH_CELLS {
+            self.full_depth()
+        } else {
+            self.full_depth().min(CAPPED_SEARCH_DEPTH)
         }
+    }
 
-        if let Some(player) = self.check_winner(0, 0, 1, 1) {
-            return Some(player);
+    /// Suggests a 0-based column for `player`, `None` when nothing can be played.
+    ///
+    /// Ties go to the lowest column, except on `Easy` where `rng` picks among
+    /// every move within `EASY_SLACK` of the best.
+    pub fn best_move(
+        &self,
+        player: Player,
+        difficulty: Difficulty,
+        rng: &mut impl Rng,
+    ) -> Option<usize> {
+        let scores = self.evaluate_columns(player, difficulty.depth(self));
+        let best = scores.iter().flatten().copied().max()?;
+        let slack = if difficulty == Difficulty::Easy {
+            EASY_SLACK
+        } else {
+            0
+        };
+        let candidates = scores
+            .iter()
+            .enumerate()
+            .filter(|(_, score)| score.is_some_and(|score| score >= best - slack))
+            .map(|(column, _)| column)
+            .collect::<Vec<_>>();
+
+        match difficulty {
+            Difficulty::Easy => Some(candidates[rng.gen_range(0..candidates.len())]),
+            _ => candidates.first().copied(),
         }
+    }
 
-        if let Some(player) = self.check_winner(0, BOARD_SIZE - 1, 1, -1) {
-            return Some(player);
+    /// Score of `player` dropping a piece into `column`, playing it and then
+    /// taking it back so the search never copies the board
+    fn score_move(
+        &mut self,
+        player: Player,
+        column: usize,
+        depth: usize,
+        memo: &mut HashMap<MemoKey, i32>,
+    ) -> Option<i32> {
+        if depth == 0 || self.column_full(column) {
+            return None;
         }
+        self.play(player, column).ok()?;
+        let score = if self.winner().is_some() {
+            self.empty_cells() as i32 + 1
+        } else if self.board_full() || depth == 1 {
+            0
+        } else {
+            -self.best_score(player.opponent(), depth - 1, memo)
+        };
+        self.undo().ok()?;
+        Some(score)
+    }
 
-        None
+    fn best_score(
+        &mut self,
+        player: Player,
+        depth: usize,
+        memo: &mut HashMap<MemoKey, i32>,
+    ) -> i32 {
+        let (milk, cookie) = self.bitboards();
+        let key = (milk, cookie, player, depth);
+        if let Some(&score) = memo.get(&key) {
+            return score;
+        }
+        let score = (0..self.cols())
+            .filter_map(|column| self.score_move(player, column, depth, memo))
+            .max()
+            .unwrap_or(0);
+        memo.insert(key, score);
+        score
+    }
+
+    /// Cells holding milk and cookie pieces, bit `row * cols + column` each
+    fn bitboards(&self) -> (u128, u128) {
+        let (mut milk, mut cookie) = (0, 0);
+        for (i, cell) in self.board.iter().flatten().enumerate() {
+            match cell.0 {
+                Some(Player::Milk) => milk |= 1 << i,
+                Some(Player::Cookie) => cookie |= 1 << i,
+                None => {}
+            }
+        }
+        (milk, cookie)
+    }
+
+    /// Render the board as a standalone SVG, one circle per piece
+    pub fn to_svg(&self) -> String {
+        let (width, height) = (self.cols() * SVG_CELL, self.rows() * SVG_CELL);
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+        );
+        svg.push_str(&format!(
+            r##"<rect x="0" y="0" width="{width}" height="{height}" fill="#ffffff" stroke="#000000"/>"##
+        ));
+        for line in 1..self.cols() {
+            let offset = line * SVG_CELL;
+            svg.push_str(&format!(
+                r##"<line x1="{offset}" y1="0" x2="{offset}" y2="{height}" stroke="#000000"/>"##
+            ));
+        }
+        for line in 1..self.rows() {
+            let offset = line * SVG_CELL;
+            svg.push_str(&format!(
+                r##"<line x1="0" y1="{offset}" x2="{width}" y2="{offset}" stroke="#000000"/>"##
+            ));
+        }
+        for (row, cells) in self.board.iter().enumerate() {
+            for (column, cell) in cells.iter().enumerate() {
+                let Some(player) = cell.0 else { continue };
+                let fill = match player {
+                    Player::Milk => "#f5f5f5",
+                    Player::Cookie => "#b5651d",
+                };
+                svg.push_str(&format!(
+                    r##"<circle cx="{}" cy="{}" r="{}" fill="{fill}" stroke="#000000"/>"##,
+                    column * SVG_CELL + SVG_CELL / 2,
+                    row * SVG_CELL + SVG_CELL / 2,
+                    SVG_CELL * 2 / 5,
+                ));
+            }
+        }
+        svg.push_str("</svg>");
+        svg
     }
 
     pub fn reset(&mut self) {
-        self.board = [[Cell::default(); BOARD_SIZE]; BOARD_SIZE];
+        self.board = vec![vec![Cell::default(); self.cols()]; self.rows()];
+        self.moves.clear();
     }
 }
 
-impl Display for Connect4 {
+/// Structured view of the board for JSON clients
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BoardView {
+    /// Rows from top to bottom, `None` for empty cells
+    pub cells: Vec<Vec<Option<Player>>>,
+    pub winner: Option<Player>,
+    /// Whether each column is full and can no longer be played
+    pub columns: Vec<bool>,
+}
+
+impl From<&Connect4> for BoardView {
+    fn from(game: &Connect4) -> Self {
+        Self {
+            cells: game
+                .board
+                .iter()
+                .map(|row| row.iter().map(|cell| cell.0).collect())
+                .collect(),
+            winner: game.winner(),
+            columns: (0..game.cols())
+                .map(|column| game.column_full(column))
+                .collect(),
+        }
+    }
+}
+
+/// Orientation the board is rendered in, the game itself is never flipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Flip {
+    #[default]
+    None,
+    /// Bottom row first, for clients drawing bottom-up
+    Vertical,
+    /// Columns mirrored left to right
+    Horizontal,
+}
+
+/// `Display` of a board in a given orientation, see `Connect4::flipped`.
+///
+/// The winner line is `🥛 wins!` as the validators expect; the alternate form
+/// (`{:#}`) spells the player out too, as in `🥛 (milk) wins!`, for logs.
+pub struct FlippedBoard<'a> {
+    game: &'a Connect4,
+    flip: Flip,
+}
+
+impl Connect4 {
+    pub fn flipped(&self, flip: Flip) -> FlippedBoard<'_> {
+        FlippedBoard { game: self, flip }
+    }
+}
+
+impl Display for FlippedBoard<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for row in self.board {
+        let mut rows = self.game.board.clone();
+        match self.flip {
+            Flip::None => {}
+            Flip::Vertical => rows.reverse(),
+            Flip::Horizontal => rows.iter_mut().for_each(|row| row.reverse()),
+        }
+        for row in rows {
             write!(f, "⬜")?;
             for cell in row {
                 write!(
@@ -123,17 +541,27 @@ impl Display for Connect4 {
             }
             writeln!(f, "⬜")?;
         }
-        writeln!(f, "{}", "⬜".repeat(BOARD_SIZE + 2))?;
-        if let Some(winner) = self.winner() {
-            writeln!(f, "{} wins!", winner)?;
-        } else if self.board_full() {
+        writeln!(f, "{}", "⬜".repeat(self.game.cols() + 2))?;
+        if let Some(winner) = self.game.winner() {
+            if f.alternate() {
+                writeln!(f, "{} ({}) wins!", winner, winner.name())?;
+            } else {
+                writeln!(f, "{} wins!", winner)?;
+            }
+        } else if self.game.board_full() {
             writeln!(f, "No winner.")?;
         }
         Ok(())
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+impl Display for Connect4 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.flipped(Flip::None), f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 struct Cell(Option<Player>);
 
 impl Deref for Cell {
@@ -159,13 +587,30 @@ impl From<Player> for Cell {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Player {
     Milk,
     Cookie,
 }
 
+impl Player {
+    /// Plain-text name, matching the serialized form
+    pub fn name(&self) -> &'static str {
+        match self {
+            Player::Milk => "milk",
+            Player::Cookie => "cookie",
+        }
+    }
+
+    pub fn opponent(&self) -> Self {
+        match self {
+            Player::Milk => Player::Cookie,
+            Player::Cookie => Player::Milk,
+        }
+    }
+}
+
 impl Display for Player {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -189,7 +634,7 @@ mod tests {
 
     #[test]
     fn test_connect4() {
-        let game = Connect4::new();
+        let game = Connect4::default();
         let expected = vec![
             "⬜⬛⬛⬛⬛⬜",
             "⬜⬛⬛⬛⬛⬜",
@@ -202,7 +647,7 @@ mod tests {
 
     #[test]
     fn test_connect4_play() {
-        let mut game = Connect4::new();
+        let mut game = Connect4::default();
         let result = game.play(Player::Milk, 0);
         let expected = vec![
             "⬜⬛⬛⬛⬛⬜",
@@ -215,9 +660,84 @@ mod tests {
         assert_gamestate!(game, expected);
     }
 
+    #[test]
+    fn test_connect4_svg() {
+        let mut game = Connect4::default();
+        assert_eq!(game.to_svg().matches("<circle").count(), 0);
+        game.play(Player::Milk, 0).unwrap();
+        game.play(Player::Cookie, 2).unwrap();
+
+        let svg = game.to_svg();
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<circle").count(), 2);
+        assert_eq!(svg.matches("#b5651d").count(), 1);
+        assert_eq!(svg, game.to_svg());
+    }
+
+    #[test]
+    fn test_connect4_winner_line() {
+        let mut game = Connect4::default();
+        for _ in 0..BOARD_SIZE {
+            game.play(Player::Cookie, 3).unwrap();
+        }
+        let board = game.to_string();
+        assert_eq!(board.lines().last(), Some("🍪 wins!"));
+        let board = format!("{game:#}");
+        assert_eq!(board.lines().last(), Some("🍪 (cookie) wins!"));
+        let board = format!("{:#}", game.flipped(Flip::Vertical));
+        assert_eq!(board.lines().last(), Some("🍪 (cookie) wins!"));
+
+        for player in [Player::Milk, Player::Cookie] {
+            let name = serde_json::to_value(player).unwrap();
+            assert_eq!(name, player.name());
+        }
+    }
+
+    #[test]
+    fn test_connect4_with_size() {
+        let mut game = Connect4::with_size(6, 7);
+        assert_eq!((game.rows(), game.cols()), (6, 7));
+        assert!(game.play(Player::Milk, 6).is_ok());
+        assert!(game.play(Player::Milk, 7).is_err());
+        for _ in 0..5 {
+            game.play(Player::Cookie, 0).unwrap();
+        }
+        assert!(!game.column_full(0));
+        game.play(Player::Cookie, 0).unwrap();
+        assert!(game.column_full(0));
+        assert_eq!(game.winner(), Some(Player::Cookie));
+
+        let board = game.to_string();
+        let lines = board.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "⬜🍪⬛⬛⬛⬛⬛⬛⬜");
+        assert_eq!(lines[5], "⬜🍪⬛⬛⬛⬛⬛🥛⬜");
+        assert_eq!(lines[6], "⬜".repeat(9));
+
+        game.reset();
+        assert_eq!((game.rows(), game.cols()), (6, 7));
+        assert!(!game.board_full());
+    }
+
+    #[test]
+    fn test_bitboards() {
+        let mut game = Connect4::with_size(2, 3);
+        assert_eq!(game.bitboards(), (0, 0));
+        game.play(Player::Milk, 2).unwrap();
+        game.play(Player::Cookie, 2).unwrap();
+        game.play(Player::Milk, 0).unwrap();
+        // bits run row by row from the top, so the bottom row is bits 3..6
+        assert_eq!(game.bitboards(), (0b101_000, 0b000_100));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_board_too_large() {
+        Connect4::with_size(12, 11);
+    }
+
     #[test]
     fn test_connect4_play_out_of_bounds() {
-        let mut game = Connect4::new();
+        let mut game = Connect4::default();
         let result = game.play(Player::Milk, 10);
         let expected = vec![
             "⬜⬛⬛⬛⬛⬜",
@@ -232,7 +752,7 @@ mod tests {
 
     #[test]
     fn test_connect4_play_full_column() {
-        let mut game = Connect4::new();
+        let mut game = Connect4::default();
         assert!(game.play(Player::Milk, 0).is_ok());
         assert!(game.play(Player::Cookie, 0).is_ok());
         assert!(game.play(Player::Milk, 0).is_ok());
@@ -251,7 +771,7 @@ mod tests {
 
     #[test]
     fn test_connect4_play_full_board() {
-        let mut game = Connect4::new();
+        let mut game = Connect4::default();
         for col in 0..BOARD_SIZE {
             for _ in 0..BOARD_SIZE {
                 assert!(game.play(Player::Milk, col).is_ok());
@@ -270,7 +790,7 @@ mod tests {
 
     #[test]
     fn test_connect4_no_winner() {
-        let mut game = Connect4::new();
+        let game = Connect4::default();
         let expected = vec![
             "⬜⬛⬛⬛⬛⬜",
             "⬜⬛⬛⬛⬛⬜",
@@ -284,7 +804,7 @@ mod tests {
 
     #[test]
     fn test_connect4_winner_row_milk() {
-        let mut game = Connect4::new();
+        let mut game = Connect4::default();
         assert!(game.play(Player::Milk, 0).is_ok());
         assert!(game.play(Player::Milk, 1).is_ok());
         assert!(game.play(Player::Milk, 2).is_ok());
@@ -304,7 +824,7 @@ mod tests {
 
     #[test]
     fn test_connect4_winner_column_cookie() {
-        let mut game = Connect4::new();
+        let mut game = Connect4::default();
         assert!(game.play(Player::Cookie, 0).is_ok());
         assert!(game.play(Player::Cookie, 0).is_ok());
         assert!(game.play(Player::Cookie, 0).is_ok());
@@ -324,7 +844,7 @@ mod tests {
 
     #[test]
     fn test_connect4_winner_diagonal_cookie() {
-        let mut game = Connect4::new();
+        let mut game = Connect4::default();
         assert!(game.play(Player::Cookie, 0).is_ok());
 
         assert!(game.play(Player::Milk, 1).is_ok());
@@ -354,7 +874,7 @@ mod tests {
 
     #[test]
     fn test_connect4_winner_diagonal_milk() {
-        let mut game = Connect4::new();
+        let mut game = Connect4::default();
         assert!(game.play(Player::Cookie, 0).is_ok());
         assert!(game.play(Player::Milk, 0).is_ok());
         assert!(game.play(Player::Cookie, 0).is_ok());
@@ -384,7 +904,7 @@ mod tests {
 
     #[test]
     fn test_full_board_no_winner() {
-        let mut game = Connect4::new();
+        let mut game = Connect4::default();
 
         // Fill board in a pattern that prevents any 4-in-a-row
         assert!(game.play(Player::Milk, 0).is_ok());
@@ -419,4 +939,246 @@ mod tests {
         assert_gamestate!(game, expected);
         assert_eq!(game.winner(), None);
     }
+
+    #[test]
+    fn test_connect4_winner_square_2x2() {
+        let mut game = Connect4::default().with_win_rule(WinRule::Square2x2);
+        assert!(game.play(Player::Cookie, 1).is_ok());
+        assert!(game.play(Player::Cookie, 1).is_ok());
+        assert!(game.play(Player::Cookie, 2).is_ok());
+        assert_eq!(game.winner(), None);
+        assert!(game.play(Player::Cookie, 2).is_ok());
+
+        let expected = vec![
+            "⬜⬛⬛⬛⬛⬜",
+            "⬜⬛⬛⬛⬛⬜",
+            "⬜⬛🍪🍪⬛⬜",
+            "⬜⬛🍪🍪⬛⬜",
+            "⬜⬜⬜⬜⬜⬜",
+            "🍪 wins!",
+        ];
+        assert_gamestate!(game, expected);
+        assert_eq!(
+            game.winning_line(),
+            Some((Player::Cookie, vec![(2, 1), (2, 2), (3, 1), (3, 2)]))
+        );
+    }
+
+    #[test]
+    fn test_connect4_square_2x2_mixed_players() {
+        let mut game = Connect4::default().with_win_rule(WinRule::Square2x2);
+        assert!(game.play(Player::Cookie, 0).is_ok());
+        assert!(game.play(Player::Cookie, 0).is_ok());
+        assert!(game.play(Player::Cookie, 1).is_ok());
+        assert!(game.play(Player::Milk, 1).is_ok());
+        assert_eq!(game.winner(), None);
+    }
+
+    #[test]
+    fn test_connect4_winner_four_corners() {
+        let mut game = Connect4::default().with_win_rule(WinRule::FourCorners);
+        for _ in 0..BOARD_SIZE {
+            assert!(game.play(Player::Milk, 0).is_ok());
+            assert!(game.play(Player::Milk, BOARD_SIZE - 1).is_ok());
+        }
+        assert_eq!(game.winner(), Some(Player::Milk));
+    }
+
+    #[test]
+    fn test_connect4_default_rule_ignores_square() {
+        let mut game = Connect4::default();
+        assert!(game.play(Player::Milk, 0).is_ok());
+        assert!(game.play(Player::Milk, 0).is_ok());
+        assert!(game.play(Player::Milk, 1).is_ok());
+        assert!(game.play(Player::Milk, 1).is_ok());
+        assert_eq!(game.winner(), None);
+    }
+
+    #[test]
+    fn test_evaluate_columns_block_threat() {
+        let mut game = Connect4::default();
+        assert!(game.play(Player::Milk, 0).is_ok());
+        assert!(game.play(Player::Milk, 1).is_ok());
+        assert!(game.play(Player::Milk, 2).is_ok());
+
+        // only blocking the bottom row avoids losing on the next move
+        let scores = game.evaluate_columns(Player::Cookie, game.full_depth());
+        let block = scores[3].unwrap();
+        for score in &scores[..3] {
+            assert!(score.unwrap() < 0);
+            assert!(score.unwrap() < block);
+        }
+
+        let scores = game.evaluate_columns(Player::Milk, game.full_depth());
+        assert_eq!(scores[3], Some(13));
+    }
+
+    #[test]
+    fn test_evaluate_columns_game_over() {
+        let mut game = Connect4::default();
+        for _ in 0..BOARD_SIZE {
+            assert!(game.play(Player::Milk, 0).is_ok());
+        }
+        assert_eq!(
+            game.evaluate_columns(Player::Cookie, game.full_depth()),
+            vec![None; BOARD_SIZE]
+        );
+    }
+
+    #[test]
+    fn test_board_view_column_flags() {
+        let mut game = Connect4::default();
+        for _ in 0..BOARD_SIZE {
+            assert!(game.play(Player::Cookie, 2).is_ok());
+        }
+        assert_eq!(
+            BoardView::from(&game).columns,
+            vec![false, false, true, false]
+        );
+
+        game.reset();
+        assert_eq!(BoardView::from(&game).columns, vec![false; BOARD_SIZE]);
+    }
+
+    #[test]
+    fn test_search_depth() {
+        let mut game = Connect4::default();
+        assert_eq!(game.search_depth(), 16);
+        game.play(Player::Milk, 0).unwrap();
+        assert_eq!(game.search_depth(), 15);
+
+        let game = Connect4::with_size(6, 7);
+        assert_eq!(game.search_depth(), CAPPED_SEARCH_DEPTH);
+    }
+
+    #[test]
+    fn test_best_move_blocks_by_difficulty() {
+        use rand::SeedableRng;
+
+        let mut game = Connect4::default();
+        assert!(game.play(Player::Milk, 0).is_ok());
+        assert!(game.play(Player::Milk, 1).is_ok());
+        assert!(game.play(Player::Milk, 2).is_ok());
+
+        for difficulty in [Difficulty::Medium, Difficulty::Hard] {
+            let mut rng = StdRng::seed_from_u64(2024);
+            assert_eq!(
+                game.best_move(Player::Cookie, difficulty, &mut rng),
+                Some(3)
+            );
+        }
+
+        // easy doesn't look far enough ahead to see milk's threat
+        let easy_moves = (0..16)
+            .map(|seed| {
+                let mut rng = StdRng::seed_from_u64(seed);
+                game.best_move(Player::Cookie, Difficulty::Easy, &mut rng)
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert!(easy_moves.iter().any(|&column| column != 3));
+
+        // but still takes a win when it has one
+        let mut rng = StdRng::seed_from_u64(2024);
+        assert_eq!(
+            game.best_move(Player::Milk, Difficulty::Easy, &mut rng),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_moves_after_undo() {
+        let mut game = Connect4::default();
+        assert!(game.play(Player::Milk, 0).is_ok());
+        assert!(game.play(Player::Cookie, 0).is_ok());
+        assert!(game.play(Player::Milk, 2).is_ok());
+
+        let undone = game.undo().unwrap();
+        assert_eq!(
+            undone,
+            Move {
+                player: Player::Milk,
+                column: 2,
+                row: BOARD_SIZE - 1,
+            }
+        );
+        assert_eq!(
+            game.moves(),
+            [
+                Move {
+                    player: Player::Milk,
+                    column: 0,
+                    row: BOARD_SIZE - 1,
+                },
+                Move {
+                    player: Player::Cookie,
+                    column: 0,
+                    row: BOARD_SIZE - 2,
+                },
+            ]
+        );
+        assert_eq!(game.move_count(), 2);
+        assert!(game.board[BOARD_SIZE - 1][2].is_none());
+
+        game.reset();
+        assert!(game.moves().is_empty());
+        assert!(game.undo().is_err());
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut game = Connect4::default();
+        let players = [Player::Milk, Player::Cookie].into_iter().cycle();
+        for (player, column) in players.zip([0, 1, 0, 1, 0, 1, 0]) {
+            assert!(game.play(player, column).is_ok());
+        }
+        assert_eq!(game.winner(), Some(Player::Milk));
+        let before = game.moves()[..2].to_vec();
+
+        assert!(game.rewind(8).is_err());
+        assert!(game.rewind(2).is_ok());
+        assert_eq!(game.moves(), before);
+        assert_eq!(game.move_count(), 2);
+        assert_eq!(game.winner(), None);
+    }
+
+    #[test]
+    fn test_flipped_rendering() {
+        let mut game = Connect4::default();
+        assert!(game.play(Player::Cookie, 0).is_ok());
+        assert!(game.play(Player::Milk, 0).is_ok());
+        assert!(game.play(Player::Cookie, 1).is_ok());
+
+        let normal = concat!(
+            "⬜⬛⬛⬛⬛⬜\n",
+            "⬜⬛⬛⬛⬛⬜\n",
+            "⬜🥛⬛⬛⬛⬜\n",
+            "⬜🍪🍪⬛⬛⬜\n",
+            "⬜⬜⬜⬜⬜⬜\n",
+        );
+        assert_eq!(game.to_string(), normal);
+        assert_eq!(game.flipped(Flip::None).to_string(), normal);
+        assert_eq!(
+            game.flipped(Flip::Vertical).to_string(),
+            concat!(
+                "⬜🍪🍪⬛⬛⬜\n",
+                "⬜🥛⬛⬛⬛⬜\n",
+                "⬜⬛⬛⬛⬛⬜\n",
+                "⬜⬛⬛⬛⬛⬜\n",
+                "⬜⬜⬜⬜⬜⬜\n",
+            )
+        );
+        assert_eq!(
+            game.flipped(Flip::Horizontal).to_string(),
+            concat!(
+                "⬜⬛⬛⬛⬛⬜\n",
+                "⬜⬛⬛⬛⬛⬜\n",
+                "⬜⬛⬛⬛🥛⬜\n",
+                "⬜⬛⬛🍪🍪⬜\n",
+                "⬜⬜⬜⬜⬜⬜\n",
+            )
+        );
+        // rendering leaves the game alone
+        assert_eq!(game.to_string(), normal);
+    }
 }
@@ -1,8 +1,12 @@
 use std::fmt;
 
-use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 
+use crate::utils::error_responses::ErrorFormat;
+
+#[derive(Debug)]
 pub struct Error(anyhow::Error);
 
 impl fmt::Display for Error {
@@ -11,10 +15,25 @@ impl fmt::Display for Error {
     }
 }
 
+impl Error {
+    fn response(self, format: ErrorFormat) -> Response {
+        tracing::error!("Error: {}", self.0);
+        let mut response = format.response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &self.0.to_string(),
+            &format!("Internal server error: {}", self.0),
+        );
+        response
+            .headers_mut()
+            .entry(CONTENT_TYPE)
+            .or_insert(HeaderValue::from_static("text/plain; charset=utf-8"));
+        response
+    }
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        tracing::error!("Error: {}", self.0);
-        (StatusCode::INTERNAL_SERVER_ERROR, self.0.to_string()).into_response()
+        self.response(ErrorFormat::from_env())
     }
 }
 
@@ -28,3 +47,33 @@ where
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_text_format_internal_error() {
+        let response =
+            Error::from(anyhow::anyhow!("database unavailable")).response(ErrorFormat::Text);
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "Internal server error: database unavailable\n");
+    }
+
+    #[tokio::test]
+    async fn test_json_format_internal_error() {
+        let response =
+            Error::from(anyhow::anyhow!("database unavailable")).response(ErrorFormat::Json);
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            body,
+            r#"{"error":"Internal server error: database unavailable"}"#
+        );
+    }
+}
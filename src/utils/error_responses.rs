@@ -1,18 +1,122 @@
+use axum::http::header::CONTENT_TYPE;
 use axum::http::StatusCode;
 use axum::response::Response;
+use serde::Serialize;
+
+/// Environment variable selecting how error bodies are rendered: `text` for a
+/// human-readable reason on every error, `json` for `{"error": reason}` or
+/// `problem` for RFC 9457 problem details
+pub const ERROR_FORMAT_ENV: &str = "ERROR_FORMAT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorFormat {
+    /// Bodies as the challenge validators expect them, some of them empty
+    #[default]
+    Default,
+    /// Always a plain-text reason, for people poking at the API with curl
+    Text,
+    /// `{"error": reason}`
+    Json,
+    /// `application/problem+json` with the reason as `detail`
+    Problem,
+}
+
+impl ErrorFormat {
+    /// Format named by `ERROR_FORMAT`, failing on a value it doesn't know
+    pub fn try_from_env() -> anyhow::Result<Self> {
+        let format = std::env::var(ERROR_FORMAT_ENV).unwrap_or_default();
+        match format.to_lowercase().as_str() {
+            "" | "default" => Ok(Self::Default),
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "problem" => Ok(Self::Problem),
+            _ => Err(anyhow::anyhow!(
+                "unknown {ERROR_FORMAT_ENV} {format:?}, expected default, text, json or problem"
+            )),
+        }
+    }
+
+    /// Format named by `ERROR_FORMAT`, the default one if it isn't known;
+    /// startup rejects unknown formats through `try_from_env`
+    pub fn from_env() -> Self {
+        Self::try_from_env().unwrap_or_default()
+    }
+
+    /// Builds an error response with `body`, or `reason` in the other formats
+    pub fn response(&self, status: StatusCode, body: &str, reason: &str) -> Response {
+        let (content_type, body) = match self {
+            Self::Default => (None, body.to_string()),
+            Self::Text => (None, format!("{reason}\n")),
+            Self::Json => (
+                Some("application/json"),
+                serde_json::json!({ "error": reason }).to_string(),
+            ),
+            Self::Problem => (
+                Some("application/problem+json"),
+                serde_json::json!({
+                    "type": "about:blank",
+                    "title": status.canonical_reason(),
+                    "status": status.as_u16(),
+                    "detail": reason,
+                })
+                .to_string(),
+            ),
+        };
+        let mut response = Response::builder().status(status);
+        if let Some(content_type) = content_type {
+            response = response.header(CONTENT_TYPE, content_type);
+        }
+        response.body(body.into()).unwrap()
+    }
+
+    pub fn invalid_manifest(&self) -> Response {
+        self.response(
+            StatusCode::BAD_REQUEST,
+            "Invalid manifest",
+            "Invalid manifest",
+        )
+    }
+
+    pub fn bad_request(&self) -> Response {
+        self.response(StatusCode::BAD_REQUEST, "", "Bad request")
+    }
+
+    pub fn magic_keyword_not_provided(&self) -> Response {
+        self.response(
+            StatusCode::BAD_REQUEST,
+            "Magic keyword not provided",
+            "Magic keyword not provided",
+        )
+    }
+
+    pub fn payload_too_large(&self) -> Response {
+        self.response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "Manifest too large",
+            "Manifest too large",
+        )
+    }
+
+    pub fn unsupported_content_type(&self) -> Response {
+        self.response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "",
+            "Unsupported content type",
+        )
+    }
+}
 
 pub fn invalid_manifest() -> Response {
-    Response::builder()
-        .status(StatusCode::BAD_REQUEST)
-        .body("Invalid manifest".into())
-        .unwrap()
+    ErrorFormat::from_env().invalid_manifest()
+}
+
+pub fn bad_request() -> Response {
+    ErrorFormat::from_env().bad_request()
 }
 
 pub fn magic_keyword_not_provided() -> Response {
-    Response::builder()
-        .status(StatusCode::BAD_REQUEST)
-        .body("Magic keyword not provided".into())
-        .unwrap()
+    ErrorFormat::from_env().magic_keyword_not_provided()
 }
 
 pub fn no_content() -> Response {
@@ -22,9 +126,64 @@ pub fn no_content() -> Response {
         .unwrap()
 }
 
+pub fn payload_too_large() -> Response {
+    ErrorFormat::from_env().payload_too_large()
+}
+
 pub fn unsupported_content_type() -> Response {
+    ErrorFormat::from_env().unsupported_content_type()
+}
+
+pub fn milk_withdrawn() -> Response {
     Response::builder()
-        .status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
-        .body("".into())
+        .status(StatusCode::OK)
+        .body("Milk withdrawn\n".into())
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body(response: Response) -> String {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_text_format_invalid_manifest() {
+        let response = ErrorFormat::Text.invalid_manifest();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(body(response).await, "Invalid manifest\n");
+    }
+
+    #[tokio::test]
+    async fn test_text_format_fills_empty_bodies() {
+        assert_eq!(body(ErrorFormat::Default.bad_request()).await, "");
+        assert_eq!(body(ErrorFormat::Text.bad_request()).await, "Bad request\n");
+    }
+
+    #[tokio::test]
+    async fn test_json_formats() {
+        let response = ErrorFormat::Json.payload_too_large();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(body(response).await, r#"{"error":"Manifest too large"}"#);
+
+        let response = ErrorFormat::Problem.unsupported_content_type();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/problem+json");
+        let problem: serde_json::Value = serde_json::from_str(&body(response).await).unwrap();
+        assert_eq!(
+            problem,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Unsupported Media Type",
+                "status": 415,
+                "detail": "Unsupported content type",
+            })
+        );
+    }
+}
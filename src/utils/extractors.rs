@@ -0,0 +1,160 @@
+use std::convert::Infallible;
+
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
+use axum::http::request::Parts;
+use axum::response::Response;
+use axum::Json;
+use serde::de::DeserializeOwned;
+
+use crate::utils::error_responses::{bad_request, milk_withdrawn};
+
+/// JSON body extractor rejecting anything not sent as `application/json` with
+/// the "Milk withdrawn" response, and unparseable bodies with an empty 400.
+///
+/// Extract it as `Result<JsonOrWithdrawn<T>, Response>` to decide when the
+/// rejection is returned, e.g. only after rate limiting.
+#[derive(Debug, Clone)]
+pub struct JsonOrWithdrawn<T>(pub T);
+
+#[async_trait::async_trait]
+impl<S, T> FromRequest<S> for JsonOrWithdrawn<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_json = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .is_some_and(|content_type| content_type == "application/json");
+        if !is_json {
+            return Err(milk_withdrawn());
+        }
+
+        let Json(payload) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(|_| bad_request())?;
+        Ok(Self(payload))
+    }
+}
+
+/// Whether the `Accept` header lists `application/json`, for handlers that add
+/// a JSON body on request but otherwise keep their original response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcceptsJson(pub bool);
+
+#[async_trait::async_trait]
+impl<S> FromRequestParts<S> for AcceptsJson
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let accepts_json = parts
+            .headers
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| {
+                accept.split(',').any(|range| {
+                    range.split(';').next().unwrap_or_default().trim() == "application/json"
+                })
+            });
+        Ok(Self(accepts_json))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    async fn extract(content_type: Option<&str>, body: &str) -> (StatusCode, String) {
+        let router =
+            Router::new().route(
+                "/",
+                post(
+                    |JsonOrWithdrawn(payload): JsonOrWithdrawn<Value>| async move {
+                        payload.to_string()
+                    },
+                ),
+            );
+        let mut request = Request::post("/");
+        if let Some(content_type) = content_type {
+            request = request.header(CONTENT_TYPE, content_type);
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_json_accepted() {
+        let (status, body) = extract(Some("application/json"), r#"{"liters":1}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"liters":1}"#);
+    }
+
+    #[tokio::test]
+    async fn test_non_json_withdrawn() {
+        let (status, body) = extract(Some("text/plain"), r#"{"liters":1}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "Milk withdrawn\n");
+    }
+
+    #[tokio::test]
+    async fn test_missing_content_type_withdrawn() {
+        let (status, body) = extract(None, r#"{"liters":1}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "Milk withdrawn\n");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_json_bad_request() {
+        let (status, body) = extract(Some("application/json"), "{").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, "");
+    }
+
+    #[tokio::test]
+    async fn test_accepts_json() {
+        let router = Router::new().route(
+            "/",
+            post(|AcceptsJson(accepts_json): AcceptsJson| async move { accepts_json.to_string() }),
+        );
+        for (accept, expected) in [
+            (Some("application/json"), "true"),
+            (Some("text/html, application/json;q=0.9"), "true"),
+            (Some("text/plain"), "false"),
+            (None, "false"),
+        ] {
+            let mut request = Request::post("/");
+            if let Some(accept) = accept {
+                request = request.header(ACCEPT, accept);
+            }
+            let response = router
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, expected, "{accept:?}");
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use shuttle_persist::PersistInstance;
+use sqlx::types::chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+
+use crate::utils::connect_four::{Connect4, Player};
+use crate::utils::error_handling::Result;
+use crate::utils::persist_namespace::NamespacedPersist;
+
+/// Persist key the finished games are stored under
+const HISTORY_KEY: &str = "connect4-history";
+
+/// Environment variable holding the namespace of game keys in the shared persist store
+pub const PERSIST_PREFIX_ENV: &str = "GAME_PERSIST_PREFIX";
+
+const DEFAULT_PERSIST_PREFIX: &str = "game:";
+
+/// Number of most recent finished games kept
+pub const HISTORY_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FinishedGame {
+    /// `None` for a draw on a full board
+    pub winner: Option<Player>,
+    pub move_count: usize,
+    pub ended_at: DateTime<Utc>,
+}
+
+/// Chronological record of finished Connect-4 games, backed by the persist store
+pub struct GameHistory {
+    persist: NamespacedPersist,
+    games: Mutex<VecDeque<FinishedGame>>,
+}
+
+impl GameHistory {
+    pub fn new(persist: PersistInstance) -> Self {
+        let persist =
+            NamespacedPersist::from_env(persist, PERSIST_PREFIX_ENV, DEFAULT_PERSIST_PREFIX);
+        let games = persist.load(HISTORY_KEY).unwrap_or_default();
+        Self {
+            persist,
+            games: Mutex::new(games),
+        }
+    }
+
+    /// Records `game` if it just reached a terminal state
+    pub async fn record_if_finished(&self, game: &Connect4) -> Result<()> {
+        let winner = game.winner();
+        if winner.is_none() && !game.board_full() {
+            return Ok(());
+        }
+
+        let mut games = self.games.lock().await;
+        games.push_back(FinishedGame {
+            winner,
+            move_count: game.move_count(),
+            ended_at: Utc::now(),
+        });
+        while games.len() > HISTORY_LIMIT {
+            games.pop_front();
+        }
+        self.persist.save(HISTORY_KEY, &*games)?;
+        Ok(())
+    }
+
+    /// Finished games, oldest first
+    pub async fn list(&self) -> Vec<FinishedGame> {
+        self.games.lock().await.iter().cloned().collect()
+    }
+}
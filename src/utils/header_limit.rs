@@ -0,0 +1,76 @@
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::Response;
+
+/// Environment variable capping the total size of a request's headers in bytes
+pub const MAX_HEADER_BYTES_ENV: &str = "MAX_HEADER_BYTES";
+
+const DEFAULT_MAX_HEADER_BYTES: usize = 16 * 1024;
+
+pub fn max_header_bytes() -> usize {
+    std::env::var(MAX_HEADER_BYTES_ENV)
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_MAX_HEADER_BYTES)
+}
+
+/// Header bytes as sent on the wire, counting the `: ` and CRLF around each value
+fn header_bytes(request: &Request) -> usize {
+    request
+        .headers()
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len() + 4)
+        .sum()
+}
+
+/// Rejects requests whose headers add up to more than `max` bytes with a 431,
+/// install with `from_fn_with_state(max_header_bytes(), limit_header_bytes)`
+pub async fn limit_header_bytes(
+    State(max): State<usize>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if header_bytes(&request) > max {
+        return Response::builder()
+            .status(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+            .body(Body::empty())
+            .unwrap();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::middleware::from_fn_with_state;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    async fn status_with_header(value: &str) -> StatusCode {
+        let router = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(from_fn_with_state(1024, limit_header_bytes));
+        let request = Request::get("/")
+            .header("x-padding", value)
+            .body(Body::empty())
+            .unwrap();
+        router.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_oversized_headers_rejected() {
+        assert_eq!(
+            status_with_header(&"a".repeat(2048)).await,
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_small_headers_pass() {
+        assert_eq!(status_with_header("a").await, StatusCode::OK);
+    }
+}
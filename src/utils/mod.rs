@@ -1,7 +1,16 @@
+pub mod cache;
 pub mod cargo_manifest;
+pub mod concurrency_limit;
 pub mod connect_four;
 pub mod error_handling;
 pub mod error_responses;
+pub mod extractors;
+pub mod game_history;
+pub mod header_limit;
 pub mod network_address;
+pub mod pagination;
+pub mod persist_namespace;
 pub mod quote;
 pub mod rate_limit;
+pub mod session_store;
+pub mod utf8_body;
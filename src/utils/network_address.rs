@@ -30,6 +30,15 @@ impl IPv4Addr {
                 .map_err(|_| anyhow::anyhow!("IPv4 address overflow"))?,
         })
     }
+
+    /// XORs `bytes` with the octets used as a repeating keystream
+    pub fn keystream_xor(&self, bytes: &[u8]) -> Vec<u8> {
+        bytes
+            .iter()
+            .zip(self.octets.iter().cycle())
+            .map(|(byte, key)| byte ^ key)
+            .collect()
+    }
 }
 
 impl TryFrom<String> for IPv4Addr {
@@ -50,6 +59,102 @@ impl From<IPv4Addr> for String {
     }
 }
 
+/// IPv4 network in CIDR notation, e.g. `10.0.0.0/29`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IPv4Cidr {
+    /// Network address with the host bits cleared
+    network: u32,
+    prefix: u32,
+}
+
+/// Mask keeping the first `prefix` of `BITS` bits, `prefix` being at most `BITS`
+fn prefix_mask<const BITS: u32>(prefix: u32) -> u128 {
+    let all = u128::MAX >> (128 - BITS);
+    all.checked_shl(BITS - prefix).unwrap_or(0) & all
+}
+
+impl IPv4Cidr {
+    /// Whether `addr` falls within the network
+    pub fn contains(&self, addr: &IPv4Addr) -> bool {
+        let mask = prefix_mask::<32>(self.prefix) as u32;
+        u32::from_be_bytes(addr.octets) & mask == self.network
+    }
+
+    /// Number of usable hosts, every address for /31 and /32 networks
+    pub fn host_count(&self) -> u64 {
+        let size = 1u64 << (32 - self.prefix);
+        if self.prefix >= 31 {
+            size
+        } else {
+            size - 2
+        }
+    }
+
+    /// Usable hosts, skipping the network and broadcast addresses where they exist
+    pub fn hosts(&self) -> impl Iterator<Item = IPv4Addr> {
+        let first = if self.prefix >= 31 { 0 } else { 1 };
+        let network = self.network;
+        (first..first + self.host_count()).map(move |offset| IPv4Addr {
+            octets: (network + offset as u32).to_be_bytes(),
+        })
+    }
+}
+
+impl TryFrom<String> for IPv4Cidr {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        let (addr, prefix) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow::anyhow!("missing CIDR prefix length"))?;
+        let addr = u32::from(addr.parse::<std::net::Ipv4Addr>()?);
+        let prefix = prefix.parse::<u32>()?;
+        if prefix > 32 {
+            return Err(anyhow::anyhow!("CIDR prefix length over 32").into());
+        }
+        let mask = prefix_mask::<32>(prefix) as u32;
+        Ok(Self {
+            network: addr & mask,
+            prefix,
+        })
+    }
+}
+
+/// IPv6 network in CIDR notation, e.g. `2001:db8::/32`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IPv6Cidr {
+    /// Network address with the host bits cleared
+    network: u128,
+    prefix: u32,
+}
+
+impl IPv6Cidr {
+    /// Whether `addr` falls within the network
+    pub fn contains(&self, addr: &IPv6Addr) -> bool {
+        let addr = u128::from(std::net::Ipv6Addr::from(addr.octets));
+        addr & prefix_mask::<128>(self.prefix) == self.network
+    }
+}
+
+impl TryFrom<String> for IPv6Cidr {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        let (addr, prefix) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow::anyhow!("missing CIDR prefix length"))?;
+        let addr = u128::from(addr.parse::<std::net::Ipv6Addr>()?);
+        let prefix = prefix.parse::<u32>()?;
+        if prefix > 128 {
+            return Err(anyhow::anyhow!("CIDR prefix length over 128").into());
+        }
+        Ok(Self {
+            network: addr & prefix_mask::<128>(prefix),
+            prefix,
+        })
+    }
+}
+
 pub struct IPv6Addr {
     octets: [u16; 8],
 }
@@ -67,6 +172,20 @@ impl IPv6Addr {
                 .unwrap(),
         }
     }
+
+    /// Like `try_from`, but rejects IPv4-mapped (`::ffff:1.2.3.4`) and
+    /// IPv4-compatible (`::1.2.3.4`) addresses
+    pub fn try_from_strict(s: String) -> Result<Self> {
+        let addr = s.parse::<std::net::Ipv6Addr>()?;
+        let embeds_ipv4 = addr.to_ipv4_mapped().is_some()
+            || (addr.segments()[..6] == [0; 6] && !addr.is_unspecified() && !addr.is_loopback());
+        if embeds_ipv4 {
+            return Err(anyhow::anyhow!("IPv4-mapped or compatible address {addr}").into());
+        }
+        Ok(Self {
+            octets: addr.segments(),
+        })
+    }
 }
 
 impl TryFrom<String> for IPv6Addr {
@@ -86,3 +205,79 @@ impl From<IPv6Addr> for String {
         addr.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(cidr: &str) -> Vec<String> {
+        IPv4Cidr::try_from(cidr.to_string())
+            .unwrap()
+            .hosts()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_cidr_hosts() {
+        assert_eq!(hosts("192.168.1.5/30"), vec!["192.168.1.5", "192.168.1.6"]);
+        assert_eq!(hosts("10.0.0.7/32"), vec!["10.0.0.7"]);
+        assert_eq!(
+            IPv4Cidr::try_from("0.0.0.0/0".to_string())
+                .unwrap()
+                .host_count(),
+            (1 << 32) - 2
+        );
+    }
+
+    #[test]
+    fn test_ipv6_strict() {
+        for addr in ["::ffff:1.2.3.4", "::1.2.3.4"] {
+            assert!(IPv6Addr::try_from(addr.to_string()).is_ok(), "{addr}");
+            assert!(
+                IPv6Addr::try_from_strict(addr.to_string()).is_err(),
+                "{addr}"
+            );
+        }
+        for addr in ["::", "::1", "fe80::1", "2001:db8::ffff:1.2.3.4"] {
+            assert!(
+                IPv6Addr::try_from_strict(addr.to_string()).is_ok(),
+                "{addr}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_cidr_invalid() {
+        for cidr in ["10.0.0.0", "10.0.0.0/33", "10.0.0/8", "10.0.0.0/x"] {
+            assert!(IPv4Cidr::try_from(cidr.to_string()).is_err(), "{cidr}");
+        }
+        for cidr in ["2001:db8::", "2001:db8::/129", "10.0.0.0/8"] {
+            assert!(IPv6Cidr::try_from(cidr.to_string()).is_err(), "{cidr}");
+        }
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let contains = |cidr: &str, addr: &str| {
+            IPv4Cidr::try_from(cidr.to_string())
+                .unwrap()
+                .contains(&IPv4Addr::try_from(addr.to_string()).unwrap())
+        };
+        assert!(contains("10.0.0.0/24", "10.0.0.5"));
+        assert!(contains("10.0.0.9/24", "10.0.0.255"));
+        assert!(!contains("10.0.0.0/24", "10.0.1.0"));
+        assert!(contains("0.0.0.0/0", "255.255.255.255"));
+        assert!(!contains("10.0.0.7/32", "10.0.0.8"));
+
+        let contains_v6 = |cidr: &str, addr: &str| {
+            IPv6Cidr::try_from(cidr.to_string())
+                .unwrap()
+                .contains(&IPv6Addr::try_from(addr.to_string()).unwrap())
+        };
+        assert!(contains_v6("2001:db8::/32", "2001:db8:ffff::1"));
+        assert!(!contains_v6("2001:db8::/32", "2001:db9::1"));
+        assert!(contains_v6("::/0", "ffff::1"));
+        assert!(contains_v6("::1/128", "::1"));
+    }
+}
@@ -0,0 +1,135 @@
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::utils::error_handling::Result;
+use crate::utils::persist_namespace::NamespacedPersist;
+
+/// Persist key prefix for page tokens
+const PAGE_TOKEN_PREFIX: &str = "page-token-";
+
+/// Fixed-size pages of a list, continued through one-time tokens kept in the
+/// persist store
+#[derive(Debug, Clone)]
+pub struct Paginator {
+    persist: NamespacedPersist,
+    page_size: i32,
+}
+
+impl Paginator {
+    pub fn new(persist: NamespacedPersist, page_size: i32) -> Self {
+        Self {
+            persist,
+            page_size: page_size.max(1),
+        }
+    }
+
+    pub fn page_size(&self) -> i32 {
+        self.page_size
+    }
+
+    /// Offset of the first item on the 1-based `page`
+    pub fn offset(&self, page: i32) -> i32 {
+        (page.max(1) - 1) * self.page_size
+    }
+
+    /// Items to fetch for a page, one more than fits so `paginate` can tell
+    /// whether another page follows
+    pub fn fetch_limit(&self) -> i32 {
+        self.page_size + 1
+    }
+
+    /// Page a `token` continues from, the first page without one and `None`
+    /// for tokens that are unknown or already used
+    pub fn page(&self, token: Option<String>) -> Result<Option<i32>> {
+        match token {
+            Some(token) => self.redeem_token(&token),
+            None => Ok(Some(1)),
+        }
+    }
+
+    /// Trims up to `fetch_limit` items fetched for `page`, minting a token for
+    /// the next page when the extra item shows there is one
+    pub fn paginate<T>(&self, mut items: Vec<T>, page: i32) -> Result<(Vec<T>, Option<String>)> {
+        if items.len() as i32 <= self.page_size {
+            return Ok((items, None));
+        }
+        items.truncate(self.page_size as usize);
+        Ok((items, Some(self.create_token(page + 1)?)))
+    }
+
+    pub fn create_token(&self, page: i32) -> Result<String> {
+        let token = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect::<String>();
+        self.persist
+            .save(&format!("{PAGE_TOKEN_PREFIX}{token}"), page)?;
+        Ok(token)
+    }
+
+    pub fn redeem_token(&self, token: &str) -> Result<Option<i32>> {
+        let key = format!("{PAGE_TOKEN_PREFIX}{token}");
+        let Ok(page) = self.persist.load::<i32>(&key) else {
+            return Ok(None);
+        };
+        // tokens are only one-time use
+        self.persist.remove(&key)?;
+        Ok(Some(page))
+    }
+
+    /// Drops every page token, leaving the rest of the namespace alone
+    pub fn reset_tokens(&self) -> Result<()> {
+        for key in self.persist.list()? {
+            if key.starts_with(PAGE_TOKEN_PREFIX) {
+                self.persist.remove(&key)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shuttle_persist::PersistInstance;
+
+    use super::*;
+
+    use crate::utils::session_store::random_id;
+
+    fn paginator(name: &str, page_size: i32) -> Paginator {
+        let dir = std::env::temp_dir().join(format!("cch24-pagination-{name}-{}", random_id()));
+        let persist = NamespacedPersist::new(PersistInstance::new(dir).unwrap(), "test:");
+        Paginator::new(persist, page_size)
+    }
+
+    #[test]
+    fn test_token_round_trip() {
+        let paginator = paginator("round-trip", 3);
+        assert_eq!(paginator.page(None).unwrap(), Some(1));
+
+        let token = paginator.create_token(2).unwrap();
+        assert_eq!(paginator.page(Some(token.clone())).unwrap(), Some(2));
+        assert_eq!(paginator.page(Some(token)).unwrap(), None);
+        assert_eq!(paginator.page(Some("unknown".to_string())).unwrap(), None);
+
+        let token = paginator.create_token(3).unwrap();
+        paginator.reset_tokens().unwrap();
+        assert_eq!(paginator.redeem_token(&token).unwrap(), None);
+    }
+
+    #[test]
+    fn test_offsets_at_page_boundaries() {
+        let paginator = paginator("offsets", 3);
+        assert_eq!(paginator.offset(1), 0);
+        assert_eq!(paginator.offset(2), 3);
+        assert_eq!(paginator.offset(3), 6);
+        assert_eq!(paginator.fetch_limit(), 4);
+
+        let (items, next) = paginator.paginate(vec![1, 2, 3], 1).unwrap();
+        assert_eq!((items, next), (vec![1, 2, 3], None));
+
+        let (items, next) = paginator.paginate(vec![4, 5, 6, 7], 2).unwrap();
+        assert_eq!(items, vec![4, 5, 6]);
+        assert_eq!(paginator.page(next).unwrap(), Some(3));
+    }
+}
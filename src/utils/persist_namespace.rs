@@ -0,0 +1,123 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use shuttle_persist::{PersistError, PersistInstance};
+
+/// View of the shared persist store where every key is prefixed, so modules
+/// using the same logical key don't overwrite each other
+#[derive(Debug, Clone)]
+pub struct NamespacedPersist {
+    persist: PersistInstance,
+    prefix: String,
+}
+
+impl NamespacedPersist {
+    pub fn new(persist: PersistInstance, prefix: impl Into<String>) -> Self {
+        Self {
+            persist,
+            prefix: prefix.into(),
+        }
+    }
+
+    /// Namespace with the prefix in `env`, or `default` when it is unset
+    pub fn from_env(persist: PersistInstance, env: &str, default: &str) -> Self {
+        let prefix = std::env::var(env).unwrap_or_else(|_| default.to_string());
+        Self::new(persist, prefix)
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    pub fn save<T: Serialize>(&self, key: &str, value: T) -> Result<(), PersistError> {
+        self.persist.save(&self.key(key), value)
+    }
+
+    /// Values saved under the bare `key` before namespacing existed are moved
+    /// under the prefix the first time they are loaded
+    pub fn load<T: DeserializeOwned + Serialize>(&self, key: &str) -> Result<T, PersistError> {
+        let error = match self.persist.load(&self.key(key)) {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        if self.prefix.is_empty() {
+            return Err(error);
+        }
+        let Ok(legacy) = self.persist.load::<T>(key) else {
+            return Err(error);
+        };
+        self.persist.save(&self.key(key), &legacy)?;
+        self.persist.remove(key)?;
+        Ok(legacy)
+    }
+
+    pub fn remove(&self, key: &str) -> Result<(), PersistError> {
+        self.persist.remove(&self.key(key))
+    }
+
+    /// Keys in this namespace, without the prefix
+    pub fn list(&self) -> Result<Vec<String>, PersistError> {
+        Ok(self
+            .persist
+            .list()?
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(&self.prefix).map(str::to_string))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::utils::session_store::random_id;
+
+    /// Persist store in a fresh directory, removed again when the guard drops
+    struct TestPersist(std::path::PathBuf);
+
+    impl TestPersist {
+        fn new() -> Self {
+            Self(std::env::temp_dir().join(format!("cch24-persist-namespace-{}", random_id())))
+        }
+
+        fn instance(&self) -> PersistInstance {
+            PersistInstance::new(self.0.clone()).unwrap()
+        }
+    }
+
+    impl Drop for TestPersist {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_namespaces_do_not_collide() {
+        let dir = TestPersist::new();
+        let persist = dir.instance();
+        let quotes = NamespacedPersist::new(persist.clone(), "quote:");
+        let games = NamespacedPersist::new(persist, "game:");
+
+        quotes.save("state", 1).unwrap();
+        games.save("state", 2).unwrap();
+        assert_eq!(quotes.load::<i32>("state").unwrap(), 1);
+        assert_eq!(games.load::<i32>("state").unwrap(), 2);
+        assert_eq!(quotes.list().unwrap(), vec!["state"]);
+
+        quotes.remove("state").unwrap();
+        assert!(quotes.load::<i32>("state").is_err());
+        assert_eq!(games.load::<i32>("state").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_legacy_keys_are_migrated() {
+        let dir = TestPersist::new();
+        let persist = dir.instance();
+        persist.save("connect4-history", vec![1, 2]).unwrap();
+        let games = NamespacedPersist::new(persist.clone(), "game:");
+
+        assert_eq!(games.load::<Vec<i32>>("connect4-history").unwrap(), [1, 2]);
+        assert!(persist.load::<Vec<i32>>("connect4-history").is_err());
+        assert_eq!(games.list().unwrap(), vec!["connect4-history"]);
+        assert!(games.load::<i32>("missing").is_err());
+    }
+}
@@ -1,6 +1,5 @@
-use std::{ops::Deref, sync::Arc};
+use std::{ops::Deref, sync::Arc, time::Duration};
 
-use rand::{distributions::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
 use shuttle_persist::PersistInstance;
 use sqlx::{
@@ -11,39 +10,222 @@ use sqlx::{
     FromRow,
 };
 
+use crate::utils::cache::TtlCache;
 use crate::utils::error_handling::Result;
+use crate::utils::pagination::Paginator;
+use crate::utils::persist_namespace::NamespacedPersist;
+use crate::utils::rate_limit::KeyedRateLimit;
 
-#[derive(Clone)]
-pub struct QuoteStateInternal {
-    pool: sqlx::PgPool,
-    persist: PersistInstance,
+/// Environment variable holding the list cache TTL in milliseconds
+pub const LIST_CACHE_TTL_ENV: &str = "QUOTE_LIST_CACHE_TTL_MS";
+
+const DEFAULT_LIST_CACHE_TTL: Duration = Duration::from_secs(2);
+
+pub fn list_cache_ttl() -> Duration {
+    std::env::var(LIST_CACHE_TTL_ENV)
+        .ok()
+        .and_then(|ttl| ttl.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_LIST_CACHE_TTL)
 }
 
-#[derive(Clone)]
-pub struct QuoteState(Arc<QuoteStateInternal>);
+/// Environment variable holding how many quotes an author may draft per minute
+pub const AUTHOR_RATE_LIMIT_ENV: &str = "QUOTE_AUTHOR_RATE_LIMIT";
 
-impl Deref for QuoteState {
-    type Target = QuoteStateInternal;
+const DEFAULT_AUTHOR_RATE_LIMIT: usize = 5;
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+pub fn author_rate_limit() -> usize {
+    std::env::var(AUTHOR_RATE_LIMIT_ENV)
+        .ok()
+        .and_then(|limit| limit.parse().ok())
+        .filter(|&limit| limit > 0)
+        .unwrap_or(DEFAULT_AUTHOR_RATE_LIMIT)
+}
+
+/// Environment variable keeping quote text out of the audit log when truthy
+pub const AUDIT_REDACT_ENV: &str = "QUOTE_AUDIT_REDACT";
+
+pub fn audit_redact() -> bool {
+    matches!(
+        std::env::var(AUDIT_REDACT_ENV)
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str(),
+        "on" | "true" | "1"
+    )
+}
+
+/// Quotes per page of `/19/list` and the other quote listings
+pub const PAGE_SIZE: i32 = 3;
+
+/// Environment variable holding the namespace of quote keys in the shared persist store
+pub const PERSIST_PREFIX_ENV: &str = "QUOTE_PERSIST_PREFIX";
+
+const DEFAULT_PERSIST_PREFIX: &str = "quote:";
+
+/// Environment variable enabling author normalization, `on` or `lowercase`
+pub const NORMALIZE_AUTHOR_ENV: &str = "QUOTE_NORMALIZE_AUTHOR";
+
+/// How authors are cleaned up before being stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthorNormalization {
+    /// Store authors as sent
+    #[default]
+    Off,
+    /// Trim and collapse internal whitespace
+    Whitespace,
+    /// Same as `Whitespace`, also lowercasing the author
+    Lowercase,
+}
+
+impl AuthorNormalization {
+    pub fn from_env() -> Self {
+        match std::env::var(NORMALIZE_AUTHOR_ENV)
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "on" | "true" | "1" => Self::Whitespace,
+            "lowercase" => Self::Lowercase,
+            _ => Self::Off,
+        }
+    }
+
+    pub fn apply(&self, author: String) -> String {
+        match self {
+            Self::Off => author,
+            Self::Whitespace => author.split_whitespace().collect::<Vec<_>>().join(" "),
+            Self::Lowercase => Self::Whitespace.apply(author).to_lowercase(),
+        }
     }
 }
 
-impl QuoteState {
-    pub fn new(pool: sqlx::PgPool, persist: PersistInstance) -> Self {
-        Self(Arc::new(QuoteStateInternal { pool, persist }))
+/// Order quotes are listed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListOrder {
+    /// Oldest first
+    #[default]
+    Oldest,
+    /// Newest first
+    Newest,
+}
+
+/// How the terms of a search combine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// Quotes matching at least one term
+    #[default]
+    Any,
+    /// Quotes matching every term
+    All,
+}
+
+/// `ILIKE` pattern matching `term` anywhere, with its own wildcards escaped
+fn like_pattern(term: &str) -> String {
+    let escaped = term
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
+/// Storage for quotes, abstracted so the handlers can run without a database
+#[async_trait::async_trait]
+pub trait QuoteStore: Send + Sync {
+    async fn reset(&self) -> Result<()>;
+    async fn get_quote(&self, id: Uuid) -> Result<Option<Quote>>;
+    /// Quotes for whichever of `ids` exist, in no particular order
+    async fn get_quotes(&self, ids: &[Uuid]) -> Result<Vec<Quote>>;
+    async fn delete_quote(&self, id: Uuid) -> Result<Option<Quote>>;
+    async fn update_quote(&self, id: Uuid, quote: QuotePayload) -> Result<Option<Quote>>;
+    async fn create_quote(&self, quote: QuotePayload) -> Result<Quote>;
+    /// Quotes at `min_version` or above (every quote for `FIRST_VERSION`),
+    /// ordered by creation time as `order` says
+    async fn list_quotes(
+        &self,
+        limit: i32,
+        offset: i32,
+        order: ListOrder,
+        min_version: i32,
+    ) -> Result<Vec<Quote>>;
+    async fn count_quotes(&self, min_version: i32) -> Result<i64>;
+    /// Most recently created quotes, newest first
+    async fn latest_quotes(&self, limit: i32) -> Result<Vec<Quote>>;
+    /// The most recently created quote by `author`
+    async fn latest_by_author(&self, author: &str) -> Result<Option<Quote>>;
+    /// Quote counts per author, most prolific first
+    async fn author_counts(&self, limit: i32, offset: i32) -> Result<Vec<AuthorCount>>;
+    /// Number of distinct authors
+    async fn count_authors(&self) -> Result<i64>;
+    async fn quote_stats(&self) -> Result<QuoteStats>;
+    /// Oldest first, quotes whose text or author contains the `terms` as
+    /// combined by `mode`, ignoring case
+    async fn search_quotes(
+        &self,
+        terms: &[String],
+        mode: SearchMode,
+        limit: i32,
+    ) -> Result<Vec<Quote>>;
+    /// When quotes were last created, edited, removed or reset, never moving
+    /// backwards; `None` before the first write
+    async fn last_modified(&self) -> Result<Option<DateTime<Utc>>>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, FromRow)]
+pub struct AuthorCount {
+    author: String,
+    count: i64,
+}
+
+/// Aggregates over every quote, zeros when there are none
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuoteStats {
+    total: i64,
+    /// Whitespace-separated words across all quotes
+    total_words: i64,
+    /// Mean quote length in characters
+    average_length: f64,
+    /// Longest quote, the oldest one on ties
+    longest_id: Option<Uuid>,
+}
+
+/// Time to stamp the next write with: now, but always a later second than
+/// `last_modified` already reports, as `Last-Modified` only has second precision
+const NEXT_MODIFIED: &str = "(SELECT greatest(now(), date_trunc('second', greatest(max(updated_at), (SELECT removed_at FROM quotes_removed))) + interval '1 second') FROM quotes)";
+
+/// Stamps a removal, before its rows are gone so it is later than theirs
+async fn mark_removed(conn: &mut sqlx::PgConnection) -> Result<()> {
+    let query = format!(
+        "INSERT INTO quotes_removed (removed_at) SELECT {NEXT_MODIFIED} ON CONFLICT (id) DO UPDATE SET removed_at = excluded.removed_at"
+    );
+    sqlx::query(&query).execute(conn).await?;
+    Ok(())
+}
+
+pub struct PgQuoteStore {
+    pool: sqlx::PgPool,
+}
+
+impl PgQuoteStore {
+    pub fn new(pool: sqlx::PgPool) -> Self {
+        Self { pool }
     }
+}
 
-    pub async fn reset(&self) -> Result<()> {
-        sqlx::query("DELETE FROM quotes")
-            .execute(&self.pool)
-            .await?;
-        self.persist.clear()?;
+#[async_trait::async_trait]
+impl QuoteStore for PgQuoteStore {
+    async fn reset(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        mark_removed(&mut tx).await?;
+        sqlx::query("DELETE FROM quotes").execute(&mut *tx).await?;
+        tx.commit().await?;
         Ok(())
     }
 
-    pub async fn get_quote(&self, id: Uuid) -> Result<Option<Quote>> {
+    async fn get_quote(&self, id: Uuid) -> Result<Option<Quote>> {
         let quote = sqlx::query_as::<_, Quote>("SELECT * FROM quotes WHERE id = $1")
             .bind(id)
             .fetch_optional(&self.pool)
@@ -51,67 +233,394 @@ impl QuoteState {
         Ok(quote)
     }
 
-    pub async fn delete_quote(&self, id: Uuid) -> Result<Option<Quote>> {
+    async fn get_quotes(&self, ids: &[Uuid]) -> Result<Vec<Quote>> {
+        let quotes = sqlx::query_as::<_, Quote>("SELECT * FROM quotes WHERE id = ANY($1)")
+            .bind(ids)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(quotes)
+    }
+
+    async fn delete_quote(&self, id: Uuid) -> Result<Option<Quote>> {
+        let mut tx = self.pool.begin().await?;
+        mark_removed(&mut tx).await?;
         let quote = sqlx::query_as::<_, Quote>("DELETE FROM quotes WHERE id = $1 RETURNING *")
             .bind(id)
-            .fetch_optional(&self.pool)
+            .fetch_optional(&mut *tx)
             .await?;
+        // nothing was removed, so the stamp is rolled back with the transaction
+        if quote.is_some() {
+            tx.commit().await?;
+        }
         Ok(quote)
     }
 
-    pub async fn update_quote(&self, id: Uuid, quote: QuotePayload) -> Result<Option<Quote>> {
-        let quote = sqlx::query_as::<_, Quote>("UPDATE quotes SET author = $1, quote = $2, version = version + 1 WHERE id = $3 RETURNING *")
-          .bind(quote.author)
-          .bind(quote.quote)
-          .bind(id)
-          .fetch_optional(&self.pool)
-          .await?;
+    /// Concurrent updates of the same quote are serialized on its row lock, so
+    /// they apply in the order they acquire it and each one observes the
+    /// previous version: two racing updates always end at `version + 2`.
+    async fn update_quote(&self, id: Uuid, quote: QuotePayload) -> Result<Option<Quote>> {
+        let mut tx = self.pool.begin().await?;
+        let locked = sqlx::query("SELECT id FROM quotes WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if locked.is_none() {
+            return Ok(None);
+        }
+
+        let query = format!("UPDATE quotes SET author = $1, quote = $2, version = version + 1, updated_at = {NEXT_MODIFIED} WHERE id = $3 RETURNING *");
+        let quote = sqlx::query_as::<_, Quote>(&query)
+            .bind(quote.author)
+            .bind(quote.quote)
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(Some(quote))
+    }
+
+    async fn create_quote(&self, quote: QuotePayload) -> Result<Quote> {
+        let query = format!(
+            "INSERT INTO quotes (author, quote, updated_at) SELECT $1, $2, {NEXT_MODIFIED} RETURNING *"
+        );
+        let quote = sqlx::query_as::<_, Quote>(&query)
+            .bind(quote.author)
+            .bind(quote.quote)
+            .fetch_one(&self.pool)
+            .await?;
         Ok(quote)
     }
 
-    pub async fn create_quote(&self, quote: QuotePayload) -> Result<Quote> {
+    async fn list_quotes(
+        &self,
+        limit: i32,
+        offset: i32,
+        order: ListOrder,
+        min_version: i32,
+    ) -> Result<Vec<Quote>> {
+        let query = match order {
+            ListOrder::Oldest => "SELECT * FROM quotes WHERE version >= $3 ORDER BY created_at ASC LIMIT $1 OFFSET $2",
+            ListOrder::Newest => "SELECT * FROM quotes WHERE version >= $3 ORDER BY created_at DESC, id DESC LIMIT $1 OFFSET $2",
+        };
+        let quotes = sqlx::query_as::<_, Quote>(query)
+            .bind(limit)
+            .bind(offset)
+            .bind(min_version)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(quotes)
+    }
+
+    async fn count_quotes(&self, min_version: i32) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM quotes WHERE version >= $1")
+            .bind(min_version)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count)
+    }
+
+    async fn latest_quotes(&self, limit: i32) -> Result<Vec<Quote>> {
+        let quotes =
+            sqlx::query_as::<_, Quote>("SELECT * FROM quotes ORDER BY created_at DESC LIMIT $1")
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(quotes)
+    }
+
+    async fn latest_by_author(&self, author: &str) -> Result<Option<Quote>> {
         let quote = sqlx::query_as::<_, Quote>(
-            "INSERT INTO quotes (author, quote) VALUES ($1, $2) RETURNING *",
+            "SELECT * FROM quotes WHERE author = $1 ORDER BY created_at DESC LIMIT 1",
         )
-        .bind(quote.author)
-        .bind(quote.quote)
-        .fetch_one(&self.pool)
+        .bind(author)
+        .fetch_optional(&self.pool)
         .await?;
         Ok(quote)
     }
 
-    pub async fn list_quotes(&self, limit: i32, offset: i32) -> Result<Vec<Quote>> {
-        let quotes = sqlx::query_as::<_, Quote>(
-            "SELECT * FROM quotes ORDER BY created_at ASC LIMIT $1 OFFSET $2",
+    async fn author_counts(&self, limit: i32, offset: i32) -> Result<Vec<AuthorCount>> {
+        let authors = sqlx::query_as::<_, AuthorCount>(
+            "SELECT author, COUNT(*) AS count FROM quotes GROUP BY author ORDER BY count DESC, author ASC LIMIT $1 OFFSET $2",
         )
         .bind(limit)
         .bind(offset)
         .fetch_all(&self.pool)
         .await?;
-        Ok(quotes)
+        Ok(authors)
     }
 
-    pub fn get_next_page_token(&self, token: String) -> Result<Option<i32>> {
-        let Ok(page) = self.persist.load::<i32>(&token) else {
-            return Ok(None);
+    async fn count_authors(&self) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(DISTINCT author) FROM quotes")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count)
+    }
+
+    async fn quote_stats(&self) -> Result<QuoteStats> {
+        let (total, total_words, average_length) = sqlx::query_as::<_, (i64, i64, f64)>(
+            r"SELECT COUNT(*),
+                COALESCE(SUM(cardinality(array_remove(regexp_split_to_array(quote, '\s+'), ''))), 0)::int8,
+                COALESCE(AVG(length(quote)), 0)::float8
+            FROM quotes",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        let longest_id = sqlx::query_scalar::<_, Uuid>(
+            "SELECT id FROM quotes ORDER BY length(quote) DESC, created_at ASC LIMIT 1",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(QuoteStats {
+            total,
+            total_words,
+            average_length,
+            longest_id,
+        })
+    }
+
+    async fn search_quotes(
+        &self,
+        terms: &[String],
+        mode: SearchMode,
+        limit: i32,
+    ) -> Result<Vec<Quote>> {
+        let joiner = match mode {
+            SearchMode::Any => " OR ",
+            SearchMode::All => " AND ",
         };
-        // tokens are only one-time use
-        self.persist.remove(&token)?;
-        Ok(Some(page))
+        let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT * FROM quotes WHERE ");
+        for (i, term) in terms.iter().enumerate() {
+            if i > 0 {
+                query.push(joiner);
+            }
+            let pattern = like_pattern(term);
+            query
+                .push("(quote ILIKE ")
+                .push_bind(pattern.clone())
+                .push(" ESCAPE '\\' OR author ILIKE ")
+                .push_bind(pattern)
+                .push(" ESCAPE '\\')");
+        }
+        query
+            .push(" ORDER BY created_at ASC, id ASC LIMIT ")
+            .push_bind(limit);
+        let quotes = query
+            .build_query_as::<Quote>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(quotes)
+    }
+
+    /// Every write stamps `updated_at` no earlier than `created_at`, so the
+    /// newest of those and the last removal is the latest write
+    async fn last_modified(&self) -> Result<Option<DateTime<Utc>>> {
+        let last_modified = sqlx::query_scalar::<_, Option<DateTime<Utc>>>(
+            "SELECT greatest((SELECT max(updated_at) FROM quotes), (SELECT removed_at FROM quotes_removed))",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(last_modified)
+    }
+}
+
+pub struct QuoteStateInternal {
+    store: Box<dyn QuoteStore>,
+    paginator: Paginator,
+    author_normalization: AuthorNormalization,
+    /// Pages of quotes keyed by `(limit, offset, order, min_version)`
+    list_cache: TtlCache<(i32, i32, ListOrder, i32), Vec<Quote>>,
+    /// Drafts allowed per (normalized) author
+    draft_limit: KeyedRateLimit,
+    /// Leave quote text out of audit events
+    audit_redact: bool,
+}
+
+#[derive(Clone)]
+pub struct QuoteState(Arc<QuoteStateInternal>);
+
+impl Deref for QuoteState {
+    type Target = QuoteStateInternal;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl QuoteState {
+    pub fn new(store: Box<dyn QuoteStore>, persist: PersistInstance) -> Self {
+        Self(Arc::new(QuoteStateInternal {
+            store,
+            paginator: Paginator::new(
+                NamespacedPersist::from_env(persist, PERSIST_PREFIX_ENV, DEFAULT_PERSIST_PREFIX),
+                PAGE_SIZE,
+            ),
+            author_normalization: AuthorNormalization::from_env(),
+            list_cache: TtlCache::new(list_cache_ttl()),
+            draft_limit: KeyedRateLimit::per_minute(author_rate_limit()),
+            audit_redact: audit_redact(),
+        }))
+    }
+
+    /// Drops cached pages after a write
+    fn mutated(&self) {
+        self.list_cache.invalidate();
+    }
+
+    /// Emits the audit trail event for a mutated quote, filterable on `audit=true`
+    fn audit(&self, action: &str, quote: &Quote) {
+        if self.audit_redact {
+            tracing::info!(
+                audit = true,
+                action,
+                id = %quote.id,
+                author = %quote.author,
+                "quote mutated"
+            );
+        } else {
+            tracing::info!(
+                audit = true,
+                action,
+                id = %quote.id,
+                author = %quote.author,
+                quote = %quote.quote,
+                "quote mutated"
+            );
+        }
+    }
+
+    /// Counts a draft against its author, returning how long to wait when the
+    /// author has drafted too many quotes recently
+    pub fn throttle_draft(&self, quote: &QuotePayload) -> Option<Duration> {
+        let author = self.author_normalization.apply(quote.author.clone());
+        self.draft_limit.try_acquire(&author).err()
+    }
+
+    pub async fn reset(&self) -> Result<()> {
+        self.store.reset().await?;
+        self.mutated();
+        self.reset_tokens()
+    }
+
+    /// Drops every page token, leaving the quotes themselves alone
+    pub fn reset_tokens(&self) -> Result<()> {
+        self.paginator.reset_tokens()
+    }
+
+    pub fn paginator(&self) -> &Paginator {
+        &self.paginator
+    }
+
+    pub async fn get_quote(&self, id: Uuid) -> Result<Option<Quote>> {
+        self.store.get_quote(id).await
+    }
+
+    pub async fn get_quotes(&self, ids: &[Uuid]) -> Result<Vec<Quote>> {
+        self.store.get_quotes(ids).await
+    }
+
+    pub async fn delete_quote(&self, id: Uuid) -> Result<Option<Quote>> {
+        let quote = self.store.delete_quote(id).await?;
+        if let Some(quote) = &quote {
+            self.mutated();
+            self.audit("delete", quote);
+        }
+        Ok(quote)
+    }
+
+    pub async fn update_quote(&self, id: Uuid, quote: QuotePayload) -> Result<Option<Quote>> {
+        let quote = self.store.update_quote(id, self.normalized(quote)).await?;
+        if let Some(quote) = &quote {
+            self.mutated();
+            self.audit("update", quote);
+        }
+        Ok(quote)
+    }
+
+    pub async fn create_quote(&self, quote: QuotePayload) -> Result<Quote> {
+        let quote = self.store.create_quote(self.normalized(quote)).await?;
+        self.mutated();
+        self.audit("create", &quote);
+        Ok(quote)
+    }
+
+    fn normalized(&self, quote: QuotePayload) -> QuotePayload {
+        QuotePayload {
+            author: self.author_normalization.apply(quote.author),
+            quote: quote.quote,
+        }
+    }
+
+    pub async fn list_quotes(
+        &self,
+        limit: i32,
+        offset: i32,
+        order: ListOrder,
+        min_version: i32,
+    ) -> Result<Vec<Quote>> {
+        let key = (limit, offset, order, min_version);
+        if let Some(quotes) = self.list_cache.get(&key) {
+            return Ok(quotes);
+        }
+
+        let generation = self.list_cache.generation();
+        let quotes = self
+            .store
+            .list_quotes(limit, offset, order, min_version)
+            .await?;
+        self.list_cache.insert(key, quotes.clone(), generation);
+        Ok(quotes)
+    }
+
+    /// Newest quotes first, ties broken by id so pages never overlap
+    pub async fn list_recent(&self, limit: i32, offset: i32) -> Result<Vec<Quote>> {
+        self.list_quotes(limit, offset, ListOrder::Newest, FIRST_VERSION)
+            .await
+    }
+
+    pub async fn count_quotes(&self, min_version: i32) -> Result<i64> {
+        self.store.count_quotes(min_version).await
+    }
+
+    pub async fn last_modified(&self) -> Result<Option<DateTime<Utc>>> {
+        self.store.last_modified().await
+    }
+
+    pub async fn latest_quotes(&self, limit: i32) -> Result<Vec<Quote>> {
+        self.store.latest_quotes(limit).await
+    }
+
+    pub async fn author_counts(&self, limit: i32, offset: i32) -> Result<Vec<AuthorCount>> {
+        self.store.author_counts(limit, offset).await
+    }
+
+    pub async fn count_authors(&self) -> Result<i64> {
+        self.store.count_authors().await
+    }
+
+    pub async fn quote_stats(&self) -> Result<QuoteStats> {
+        self.store.quote_stats().await
     }
 
-    pub fn create_next_page_token(&self, page: i32) -> Result<String> {
-        let token = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(16)
-            .map(char::from)
-            .collect::<String>();
-        self.persist.save(&token, page)?;
-        Ok(token)
+    pub async fn search_quotes(
+        &self,
+        terms: &[String],
+        mode: SearchMode,
+        limit: i32,
+    ) -> Result<Vec<Quote>> {
+        self.store.search_quotes(terms, mode, limit).await
+    }
+
+    /// Looks `author` up the same way authors are normalized when stored
+    pub async fn latest_by_author(&self, author: &str) -> Result<Option<Quote>> {
+        let author = self.author_normalization.apply(author.to_string());
+        self.store.latest_by_author(&author).await
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, FromRow)]
+/// Version a quote is created at, bumped by every edit
+pub const FIRST_VERSION: i32 = 1;
+
+#[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
 pub struct Quote {
     id: Uuid,
     author: String,
@@ -120,25 +629,538 @@ pub struct Quote {
     version: i32,
 }
 
+impl Quote {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    pub fn quote(&self) -> &str {
+        &self.quote
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct QuotePayload {
     author: String,
     quote: String,
 }
 
+/// Shape version of the list response, bumped on breaking changes
+pub const LIST_RESPONSE_VERSION: u32 = 1;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ListResponse {
     quotes: Vec<Quote>,
     page: i32,
     next_token: Option<String>,
+    /// Number of quotes across all pages
+    total: i64,
+    /// Number of pages of `page_size` quotes, 0 when there are none
+    total_pages: i64,
+    version: u32,
 }
 
 impl ListResponse {
-    pub fn new(quotes: Vec<Quote>, page: i32, next_token: Option<String>) -> Self {
+    pub fn new(
+        quotes: Vec<Quote>,
+        page: i32,
+        next_token: Option<String>,
+        total: i64,
+        page_size: i32,
+    ) -> Self {
         Self {
             quotes,
             page,
             next_token,
+            total,
+            total_pages: (total.max(0) as u64).div_ceil(page_size.max(1) as u64) as i64,
+            version: LIST_RESPONSE_VERSION,
+        }
+    }
+}
+
+/// `HashMap`-backed store for running the quote handlers without a database
+#[cfg(test)]
+pub mod in_memory {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use tokio::sync::Mutex;
+
+    use super::*;
+
+    type Quotes = HashMap<Uuid, (u64, Quote, DateTime<Utc>)>;
+
+    /// Each quote is tagged with its insertion order to keep listing stable,
+    /// and with when it was last created or edited
+    #[derive(Default)]
+    pub struct InMemoryQuoteStore {
+        quotes: Mutex<Quotes>,
+        inserted: AtomicU64,
+        /// Stamp of the last removal, always locked after `quotes`
+        removed_at: Mutex<Option<DateTime<Utc>>>,
+    }
+
+    impl InMemoryQuoteStore {
+        async fn last_modified_in(&self, quotes: &Quotes) -> Option<DateTime<Utc>> {
+            let removed_at = *self.removed_at.lock().await;
+            quotes
+                .values()
+                .map(|(_, _, updated_at)| *updated_at)
+                .chain(removed_at)
+                .max()
+        }
+
+        /// Stamps the next write the way `NEXT_MODIFIED` does
+        async fn next_modified(&self, quotes: &Quotes) -> DateTime<Utc> {
+            let now = Utc::now();
+            self.last_modified_in(quotes)
+                .await
+                .and_then(|last| DateTime::from_timestamp(last.timestamp() + 1, 0))
+                .map_or(now, |next| now.max(next))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl QuoteStore for InMemoryQuoteStore {
+        async fn reset(&self) -> Result<()> {
+            let mut quotes = self.quotes.lock().await;
+            let removed_at = self.next_modified(&quotes).await;
+            *self.removed_at.lock().await = Some(removed_at);
+            quotes.clear();
+            Ok(())
+        }
+
+        async fn get_quote(&self, id: Uuid) -> Result<Option<Quote>> {
+            let quotes = self.quotes.lock().await;
+            Ok(quotes.get(&id).map(|(_, quote, _)| quote.clone()))
+        }
+
+        async fn get_quotes(&self, ids: &[Uuid]) -> Result<Vec<Quote>> {
+            let quotes = self.quotes.lock().await;
+            Ok(ids
+                .iter()
+                .filter_map(|id| quotes.get(id).map(|(_, quote, _)| quote.clone()))
+                .collect())
+        }
+
+        async fn delete_quote(&self, id: Uuid) -> Result<Option<Quote>> {
+            let mut quotes = self.quotes.lock().await;
+            if !quotes.contains_key(&id) {
+                return Ok(None);
+            }
+            let removed_at = self.next_modified(&quotes).await;
+            *self.removed_at.lock().await = Some(removed_at);
+            Ok(quotes.remove(&id).map(|(_, quote, _)| quote))
+        }
+
+        async fn update_quote(&self, id: Uuid, payload: QuotePayload) -> Result<Option<Quote>> {
+            let mut quotes = self.quotes.lock().await;
+            if !quotes.contains_key(&id) {
+                return Ok(None);
+            }
+            let updated_at = self.next_modified(&quotes).await;
+            let Some((_, quote, stamp)) = quotes.get_mut(&id) else {
+                return Ok(None);
+            };
+            quote.author = payload.author;
+            quote.quote = payload.quote;
+            quote.version += 1;
+            *stamp = updated_at;
+            Ok(Some(quote.clone()))
+        }
+
+        async fn create_quote(&self, payload: QuotePayload) -> Result<Quote> {
+            let mut quotes = self.quotes.lock().await;
+            let quote = Quote {
+                id: Uuid::new_v4(),
+                author: payload.author,
+                quote: payload.quote,
+                created_at: Utc::now(),
+                version: FIRST_VERSION,
+            };
+            let updated_at = self.next_modified(&quotes).await;
+            let order = self.inserted.fetch_add(1, Ordering::SeqCst);
+            quotes.insert(quote.id, (order, quote.clone(), updated_at));
+            Ok(quote)
+        }
+
+        async fn list_quotes(
+            &self,
+            limit: i32,
+            offset: i32,
+            order: ListOrder,
+            min_version: i32,
+        ) -> Result<Vec<Quote>> {
+            let quotes = self.quotes.lock().await;
+            let mut ordered = quotes
+                .values()
+                .filter(|(_, quote, _)| quote.version >= min_version)
+                .collect::<Vec<_>>();
+            ordered.sort_by_key(|(inserted, _, _)| *inserted);
+            if order == ListOrder::Newest {
+                ordered.reverse();
+            }
+            Ok(ordered
+                .into_iter()
+                .skip(offset.max(0) as usize)
+                .take(limit.max(0) as usize)
+                .map(|(_, quote, _)| quote.clone())
+                .collect())
+        }
+
+        async fn count_quotes(&self, min_version: i32) -> Result<i64> {
+            let quotes = self.quotes.lock().await;
+            let count = quotes
+                .values()
+                .filter(|(_, quote, _)| quote.version >= min_version)
+                .count();
+            Ok(count as i64)
+        }
+
+        async fn latest_quotes(&self, limit: i32) -> Result<Vec<Quote>> {
+            let quotes = self.quotes.lock().await;
+            let mut ordered = quotes.values().collect::<Vec<_>>();
+            ordered.sort_by_key(|(order, _, _)| std::cmp::Reverse(*order));
+            Ok(ordered
+                .into_iter()
+                .take(limit.max(0) as usize)
+                .map(|(_, quote, _)| quote.clone())
+                .collect())
+        }
+
+        async fn latest_by_author(&self, author: &str) -> Result<Option<Quote>> {
+            let quotes = self.quotes.lock().await;
+            Ok(quotes
+                .values()
+                .filter(|(_, quote, _)| quote.author == author)
+                .max_by_key(|(order, _, _)| *order)
+                .map(|(_, quote, _)| quote.clone()))
+        }
+
+        async fn author_counts(&self, limit: i32, offset: i32) -> Result<Vec<AuthorCount>> {
+            let quotes = self.quotes.lock().await;
+            let mut counts = HashMap::<&str, i64>::new();
+            for (_, quote, _) in quotes.values() {
+                *counts.entry(&quote.author).or_default() += 1;
+            }
+            let mut counts = counts
+                .into_iter()
+                .map(|(author, count)| AuthorCount {
+                    author: author.to_string(),
+                    count,
+                })
+                .collect::<Vec<_>>();
+            counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.author.cmp(&b.author)));
+            Ok(counts
+                .into_iter()
+                .skip(offset.max(0) as usize)
+                .take(limit.max(0) as usize)
+                .collect())
+        }
+
+        async fn count_authors(&self) -> Result<i64> {
+            let quotes = self.quotes.lock().await;
+            let authors = quotes
+                .values()
+                .map(|(_, quote, _)| quote.author.as_str())
+                .collect::<std::collections::HashSet<_>>();
+            Ok(authors.len() as i64)
+        }
+
+        async fn quote_stats(&self) -> Result<QuoteStats> {
+            let quotes = self.quotes.lock().await;
+            let mut ordered = quotes.values().collect::<Vec<_>>();
+            ordered.sort_by_key(|(inserted, _, _)| *inserted);
+
+            let lengths = ordered
+                .iter()
+                .map(|(_, quote, _)| quote.quote.chars().count())
+                .collect::<Vec<_>>();
+            let total_words = ordered
+                .iter()
+                .map(|(_, quote, _)| quote.quote.split_whitespace().count())
+                .sum::<usize>();
+            // `max_by_key` keeps the last maximum, so walk newest first
+            let longest_id = ordered
+                .iter()
+                .zip(&lengths)
+                .rev()
+                .max_by_key(|(_, length)| **length)
+                .map(|((_, quote, _), _)| quote.id);
+            let average_length = if lengths.is_empty() {
+                0.0
+            } else {
+                lengths.iter().sum::<usize>() as f64 / lengths.len() as f64
+            };
+            Ok(QuoteStats {
+                total: ordered.len() as i64,
+                total_words: total_words as i64,
+                average_length,
+                longest_id,
+            })
+        }
+
+        async fn search_quotes(
+            &self,
+            terms: &[String],
+            mode: SearchMode,
+            limit: i32,
+        ) -> Result<Vec<Quote>> {
+            let terms = terms
+                .iter()
+                .map(|term| term.to_lowercase())
+                .collect::<Vec<_>>();
+            let matches = |quote: &Quote| {
+                let (text, author) = (quote.quote.to_lowercase(), quote.author.to_lowercase());
+                let contains = |term: &String| text.contains(term) || author.contains(term);
+                match mode {
+                    SearchMode::Any => terms.iter().any(contains),
+                    SearchMode::All => terms.iter().all(contains),
+                }
+            };
+
+            let quotes = self.quotes.lock().await;
+            let mut ordered = quotes
+                .values()
+                .filter(|(_, quote, _)| matches(quote))
+                .collect::<Vec<_>>();
+            ordered.sort_by_key(|(inserted, _, _)| *inserted);
+            Ok(ordered
+                .into_iter()
+                .take(limit.max(0) as usize)
+                .map(|(_, quote, _)| quote.clone())
+                .collect())
+        }
+
+        async fn last_modified(&self) -> Result<Option<DateTime<Utc>>> {
+            let quotes = self.quotes.lock().await;
+            Ok(self.last_modified_in(&quotes).await)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::utils::session_store::random_id;
+
+    #[test]
+    fn test_like_pattern_escapes_wildcards() {
+        assert_eq!(like_pattern("ada"), "%ada%");
+        assert_eq!(like_pattern(r"100%_\"), r"%100\%\_\\%");
+    }
+
+    #[test]
+    fn test_author_normalization_off() {
+        let author = AuthorNormalization::Off.apply(" Ada  Lovelace ".to_string());
+        assert_eq!(author, " Ada  Lovelace ");
+    }
+
+    #[test]
+    fn test_author_normalization_whitespace() {
+        let author = AuthorNormalization::Whitespace.apply(" Ada  Lovelace ".to_string());
+        assert_eq!(author, "Ada Lovelace");
+    }
+
+    #[test]
+    fn test_author_normalization_lowercase() {
+        let author = AuthorNormalization::Lowercase.apply(" Ada \t Lovelace ".to_string());
+        assert_eq!(author, "ada lovelace");
+    }
+
+    fn payload(author: &str) -> QuotePayload {
+        QuotePayload {
+            author: author.to_string(),
+            quote: "Ho ho ho".to_string(),
+        }
+    }
+
+    fn test_persist(name: &str) -> PersistInstance {
+        let dir = std::env::temp_dir().join(format!("cch24-quote-{name}-{}", random_id()));
+        PersistInstance::new(dir).unwrap()
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn test_concurrent_updates(pool: sqlx::PgPool) {
+        let store = Arc::new(PgQuoteStore::new(pool));
+        let quote = store.create_quote(payload("Santa")).await.unwrap();
+
+        let updates = ["Rudolph", "Dasher"].map(|author| {
+            let store = store.clone();
+            tokio::spawn(
+                async move { store.update_quote(quote.id, payload(author)).await.unwrap() },
+            )
+        });
+        let mut versions = vec![];
+        for update in updates {
+            versions.push(update.await.unwrap().unwrap().version);
+        }
+        versions.sort();
+        assert_eq!(versions, vec![quote.version + 1, quote.version + 2]);
+
+        let stored = store.get_quote(quote.id).await.unwrap().unwrap();
+        assert_eq!(stored.version, quote.version + 2);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn test_pg_search_quotes(pool: sqlx::PgPool) {
+        let store = PgQuoteStore::new(pool);
+        for (author, quote) in [
+            ("Santa", "100% real"),
+            ("Dasher", "1000 reals"),
+            ("Rudolph", "snow_day"),
+            ("Comet", "snowday"),
+            ("Blitzen", "real snow"),
+        ] {
+            let payload = QuotePayload {
+                author: author.to_string(),
+                quote: quote.to_string(),
+            };
+            store.create_quote(payload).await.unwrap();
+        }
+
+        let search = |terms: &[&str], mode| {
+            let terms = terms
+                .iter()
+                .map(|term| term.to_string())
+                .collect::<Vec<_>>();
+            let store = &store;
+            async move {
+                let quotes = store.search_quotes(&terms, mode, 10).await.unwrap();
+                let mut authors = quotes
+                    .iter()
+                    .map(|quote| quote.author().to_string())
+                    .collect::<Vec<_>>();
+                authors.sort();
+                authors
+            }
+        };
+
+        // wildcards in terms match literally
+        assert_eq!(search(&["100%"], SearchMode::Any).await, ["Santa"]);
+        assert_eq!(search(&["snow_day"], SearchMode::Any).await, ["Rudolph"]);
+        assert_eq!(search(&["SANTA"], SearchMode::Any).await, ["Santa"]);
+
+        assert_eq!(
+            search(&["real", "snow"], SearchMode::Any).await,
+            ["Blitzen", "Comet", "Dasher", "Rudolph", "Santa"]
+        );
+        assert_eq!(
+            search(&["real", "snow"], SearchMode::All).await,
+            ["Blitzen"]
+        );
+        assert!(search(&["real", "comet", "day"], SearchMode::All)
+            .await
+            .is_empty());
+
+        let limited = store
+            .search_quotes(&["real".to_string()], SearchMode::Any, 2)
+            .await
+            .unwrap();
+        assert_eq!(limited.len(), 2);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn test_pg_last_modified_never_goes_back(pool: sqlx::PgPool) {
+        let store = PgQuoteStore::new(pool);
+        assert_eq!(store.last_modified().await.unwrap(), None);
+
+        let mut seen = vec![];
+        let quote = store.create_quote(payload("Santa")).await.unwrap();
+        seen.push(store.last_modified().await.unwrap().unwrap());
+        store
+            .update_quote(quote.id, payload("Rudolph"))
+            .await
+            .unwrap();
+        seen.push(store.last_modified().await.unwrap().unwrap());
+        store.delete_quote(quote.id).await.unwrap();
+        seen.push(store.last_modified().await.unwrap().unwrap());
+        // removing nothing leaves it alone
+        store.delete_quote(quote.id).await.unwrap();
+        assert_eq!(store.last_modified().await.unwrap(), seen.last().copied());
+        store.reset().await.unwrap();
+        seen.push(store.last_modified().await.unwrap().unwrap());
+
+        // whole seconds apart, as `Last-Modified` can't tell them apart otherwise
+        for pair in seen.windows(2) {
+            assert!(pair[0].timestamp() < pair[1].timestamp(), "{seen:?}");
+        }
+    }
+
+    #[test]
+    fn test_list_response_version() {
+        let response = serde_json::to_value(ListResponse::new(vec![], 1, None, 0, 3)).unwrap();
+        assert_eq!(response["version"], 1);
+        assert_eq!(response["page"], 1);
+        assert_eq!(response["total_pages"], 0);
+    }
+
+    /// Records the fields of every event as `name=value` strings
+    #[derive(Clone, Default)]
+    struct CapturedEvents(Arc<std::sync::Mutex<Vec<Vec<String>>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for CapturedEvents {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            struct Fields(Vec<String>);
+            impl tracing::field::Visit for Fields {
+                fn record_debug(
+                    &mut self,
+                    field: &tracing::field::Field,
+                    value: &dyn std::fmt::Debug,
+                ) {
+                    self.0.push(format!("{}={:?}", field.name(), value));
+                }
+            }
+
+            let mut fields = Fields(vec![]);
+            event.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_audit_event() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let events = CapturedEvents::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(events.clone()));
+
+        let state = QuoteState::new(
+            Box::new(in_memory::InMemoryQuoteStore::default()),
+            test_persist("audit"),
+        );
+        let quote = state.create_quote(payload("Santa")).await.unwrap();
+
+        let events = events.0.lock().unwrap();
+        let audit = events
+            .iter()
+            .find(|fields| fields.contains(&"audit=true".to_string()))
+            .expect("no audit event");
+        for field in [
+            "action=\"create\"".to_string(),
+            format!("id={}", quote.id),
+            "author=Santa".to_string(),
+            "quote=Ho ho ho".to_string(),
+        ] {
+            assert!(audit.contains(&field), "{field} missing from {audit:?}");
         }
     }
 }
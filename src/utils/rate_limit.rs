@@ -1,9 +1,15 @@
+use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError};
 use std::time::Duration;
 
 use leaky_bucket::RateLimiter;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Environment variable holding the auto-refill interval in seconds
+pub const AUTO_REFILL_ENV: &str = "MILK_AUTO_REFILL_SECS";
 
 #[derive(Debug, Clone)]
 pub struct RateLimit(pub Arc<Mutex<RateLimiter>>);
@@ -14,15 +20,141 @@ impl Default for RateLimit {
     }
 }
 
+impl RateLimit {
+    /// Spawns a task that tops the bucket back up to max every `interval`.
+    ///
+    /// The task only holds a weak reference to the bucket, so it exits on its
+    /// own once the router owning this state is dropped.
+    pub fn spawn_auto_refill(&self, interval: Duration) -> JoinHandle<()> {
+        let bucket = Arc::downgrade(&self.0);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // the first tick completes immediately
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(bucket) = bucket.upgrade() else {
+                    break;
+                };
+                *bucket.lock().await = filled_bucket();
+            }
+        })
+    }
+}
+
+/// Litres of milk the bucket holds when full
+pub const BUCKET_MAX: usize = 5;
+/// Litres added back every `BUCKET_REFILL_INTERVAL`
+pub const BUCKET_REFILL: usize = 1;
+pub const BUCKET_REFILL_INTERVAL: Duration = Duration::from_secs(1);
+
 pub fn filled_bucket() -> RateLimiter {
     RateLimiter::builder()
-        .max(5)
-        .refill(1)
-        .interval(Duration::from_secs(1))
-        .initial(5)
+        .max(BUCKET_MAX)
+        .refill(BUCKET_REFILL)
+        .interval(BUCKET_REFILL_INTERVAL)
+        .initial(BUCKET_MAX)
         .build()
 }
 
+/// Reads the auto-refill interval from the environment, `None` when unset or invalid
+pub fn auto_refill_interval() -> Option<Duration> {
+    let secs = std::env::var(AUTO_REFILL_ENV).ok()?.parse::<u64>().ok()?;
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Most keys `KeyedRateLimit` tracks at once
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// Permits left for one key, refilled lazily whenever the key is seen
+#[derive(Debug, Clone, Copy)]
+struct KeyBucket {
+    permits: usize,
+    /// When the last permit was added back, or the bucket was last full
+    refilled_at: Instant,
+}
+
+/// Independent buckets per key, each allowing `limit` acquisitions per minute.
+///
+/// Keys are untrusted, so at most `max_keys` buckets are kept. Full buckets
+/// behave like unseen keys and are the first to go, then the least recently
+/// refilled ones.
+#[derive(Debug)]
+pub struct KeyedRateLimit {
+    limit: usize,
+    /// Time it takes a bucket to regain a single permit
+    refill_interval: Duration,
+    max_keys: usize,
+    buckets: std::sync::Mutex<HashMap<String, KeyBucket>>,
+}
+
+impl KeyedRateLimit {
+    pub fn per_minute(limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self {
+            limit,
+            refill_interval: Duration::from_secs(60) / limit as u32,
+            max_keys: MAX_TRACKED_KEYS,
+            buckets: Default::default(),
+        }
+    }
+
+    /// Brings `bucket` up to date with the permits regained since it was last refilled
+    fn refill(&self, bucket: &mut KeyBucket, now: Instant) {
+        let regained = (now - bucket.refilled_at).as_nanos() / self.refill_interval.as_nanos();
+        let regained = usize::try_from(regained).unwrap_or(usize::MAX);
+        if bucket.permits.saturating_add(regained) >= self.limit {
+            *bucket = KeyBucket {
+                permits: self.limit,
+                refilled_at: now,
+            };
+        } else {
+            bucket.permits += regained;
+            bucket.refilled_at += self.refill_interval * regained as u32;
+        }
+    }
+
+    /// Makes room for one more key, dropping full buckets and then the
+    /// least recently refilled one
+    fn evict(&self, buckets: &mut HashMap<String, KeyBucket>, now: Instant) {
+        buckets.retain(|_, bucket| {
+            self.refill(bucket, now);
+            bucket.permits < self.limit
+        });
+        if buckets.len() < self.max_keys {
+            return;
+        }
+        let oldest = buckets
+            .iter()
+            .min_by_key(|(_, bucket)| bucket.refilled_at)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            buckets.remove(&oldest);
+        }
+    }
+
+    /// Takes a permit from `key`'s bucket, or returns how long until its next
+    /// permit when it is empty
+    pub fn try_acquire(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        // the map stays consistent even if a holder panicked
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if !buckets.contains_key(key) && buckets.len() >= self.max_keys {
+            self.evict(&mut buckets, now);
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(KeyBucket {
+            permits: self.limit,
+            refilled_at: now,
+        });
+        self.refill(bucket, now);
+        if bucket.permits == 0 {
+            return Err(self.refill_interval - (now - bucket.refilled_at));
+        }
+        bucket.permits -= 1;
+        Ok(())
+    }
+}
+
 impl Deref for RateLimit {
     type Target = Arc<Mutex<RateLimiter>>;
 
@@ -36,3 +168,74 @@ impl DerefMut for RateLimit {
         &mut self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_auto_refill_tops_up_bucket() {
+        let rate_limit = RateLimit::default();
+        let _task = rate_limit.spawn_auto_refill(Duration::from_secs(10));
+
+        assert!(rate_limit.lock().await.try_acquire(5));
+        assert!(!rate_limit.lock().await.try_acquire(1));
+
+        // the bucket's own refill only adds a single token per second
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(!rate_limit.lock().await.try_acquire(5));
+
+        tokio::time::sleep(Duration::from_secs(9)).await;
+        assert!(rate_limit.lock().await.try_acquire(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_auto_refill_stops_when_state_dropped() {
+        let rate_limit = RateLimit::default();
+        let task = rate_limit.spawn_auto_refill(Duration::from_secs(1));
+        drop(rate_limit);
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(task.is_finished());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keyed_rate_limit_per_key() {
+        let rate_limit = KeyedRateLimit::per_minute(2);
+
+        assert!(rate_limit.try_acquire("a").is_ok());
+        assert!(rate_limit.try_acquire("a").is_ok());
+        assert_eq!(rate_limit.try_acquire("a"), Err(Duration::from_secs(30)));
+        assert!(rate_limit.try_acquire("b").is_ok());
+
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert_eq!(rate_limit.try_acquire("a"), Err(Duration::from_secs(10)));
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(rate_limit.try_acquire("a").is_ok());
+        assert_eq!(rate_limit.try_acquire("a"), Err(Duration::from_secs(30)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keyed_rate_limit_bounds_keys() {
+        let rate_limit = KeyedRateLimit {
+            max_keys: 2,
+            ..KeyedRateLimit::per_minute(1)
+        };
+        let tracked = || rate_limit.buckets.lock().unwrap().len();
+
+        assert!(rate_limit.try_acquire("a").is_ok());
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(rate_limit.try_acquire("b").is_ok());
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        // "a" is the least recently refilled, so it makes room for "c"
+        assert!(rate_limit.try_acquire("c").is_ok());
+        assert_eq!(tracked(), 2);
+        assert!(rate_limit.try_acquire("a").is_ok());
+        assert!(rate_limit.try_acquire("c").is_err());
+
+        // buckets that refilled completely are dropped before anything else
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert!(rate_limit.try_acquire("d").is_ok());
+        assert_eq!(tracked(), 1);
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use rand::{distributions::Alphanumeric, Rng};
+use tokio::time::Instant;
+
+/// Random 16 character alphanumeric id
+pub fn random_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(16)
+        .map(char::from)
+        .collect()
+}
+
+/// In-memory sessions keyed by a random id, bounded to `capacity` entries.
+///
+/// Once full, the least-recently-used session is evicted to make room, but only
+/// if it has been idle for at least `idle_timeout`; otherwise creation fails.
+#[derive(Debug)]
+pub struct SessionStore<T> {
+    capacity: usize,
+    idle_timeout: Duration,
+    sessions: HashMap<String, Session<T>>,
+}
+
+#[derive(Debug)]
+struct Session<T> {
+    value: T,
+    last_access: Instant,
+}
+
+impl<T> SessionStore<T> {
+    pub fn new(capacity: usize, idle_timeout: Duration) -> Self {
+        Self {
+            capacity,
+            idle_timeout,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Stores `value` under a fresh id, `None` when the store is full of active sessions
+    pub fn create(&mut self, value: T) -> Option<String> {
+        if self.sessions.len() >= self.capacity && !self.evict_idle() {
+            return None;
+        }
+
+        let id = random_id();
+        self.sessions.insert(
+            id.clone(),
+            Session {
+                value,
+                last_access: Instant::now(),
+            },
+        );
+        Some(id)
+    }
+
+    /// Looks up a session without counting it as a use
+    pub fn get(&self, id: &str) -> Option<&T> {
+        self.sessions.get(id).map(|session| &session.value)
+    }
+
+    /// Looks up a session, marking it as recently used
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut T> {
+        let session = self.sessions.get_mut(id)?;
+        session.last_access = Instant::now();
+        Some(&mut session.value)
+    }
+
+    /// Evicts the least-recently-used session if it has been idle long enough
+    fn evict_idle(&mut self) -> bool {
+        let Some(id) = self
+            .sessions
+            .iter()
+            .min_by_key(|(_, session)| session.last_access)
+            .filter(|(_, session)| session.last_access.elapsed() >= self.idle_timeout)
+            .map(|(id, _)| id.clone())
+        else {
+            return false;
+        };
+        self.sessions.remove(&id);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDLE: Duration = Duration::from_secs(60);
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cap_rejects_active_sessions() {
+        let mut store = SessionStore::new(2, IDLE);
+        assert!(store.create(1).is_some());
+        assert!(store.create(2).is_some());
+        assert!(store.create(3).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_evicts_least_recently_used() {
+        let mut store = SessionStore::new(2, IDLE);
+        let first = store.create(1).unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let second = store.create(2).unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        // touching the first session makes the second one the least recently used
+        assert_eq!(store.get_mut(&first), Some(&mut 1));
+        tokio::time::sleep(IDLE).await;
+
+        let third = store.create(3).unwrap();
+        assert!(store.get_mut(&second).is_none());
+        assert_eq!(store.get_mut(&first), Some(&mut 1));
+        assert_eq!(store.get_mut(&third), Some(&mut 3));
+
+        // both remaining sessions were just used
+        assert!(store.create(4).is_none());
+    }
+}
@@ -0,0 +1,62 @@
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::middleware::{from_fn, Next};
+use axum::response::Response;
+use axum::routing::RouterIntoService;
+use axum::Router;
+
+/// Bodies are buffered to validate them, capped at axum's default body limit
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Wraps `service` so text and JSON bodies that are not valid UTF-8 are
+/// rejected before any handler parses them
+pub fn require_utf8(service: RouterIntoService<Body>) -> RouterIntoService<Body> {
+    Router::new()
+        .fallback_service(service)
+        .layer(from_fn(validate_utf8))
+        .into_service()
+}
+
+/// Whether the declared content type promises text
+fn is_text(request: &Request) -> bool {
+    let Some(content_type) = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    essence.starts_with("text/") || essence == "application/json" || essence.ends_with("+json")
+}
+
+async fn validate_utf8(request: Request, next: Next) -> Response {
+    if !is_text(&request) {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_BODY_BYTES).await else {
+        return Response::builder()
+            .status(StatusCode::PAYLOAD_TOO_LARGE)
+            .body(Body::empty())
+            .unwrap();
+    };
+    if std::str::from_utf8(&bytes).is_err() {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"error":"invalid_utf8"}"#.into())
+            .unwrap();
+    }
+
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}